@@ -1 +1,62 @@
 export declare function run(args?: Array<string>): Promise<void>;
+
+export interface ProbeRecord {
+  attempt: number;
+  stage: "captive_api" | "standard" | "gateway" | "dns";
+  /** Set when the probe was tied to one address family. */
+  family?: "ipv4" | "ipv6";
+  endpoint: string;
+  url: string;
+  outcome: "portal" | "authentication_required" | "ok" | "mismatch" | "body_mismatch" | "error";
+  status_code: number | null;
+  elapsed_ms: number;
+  detail: string | null;
+  /** From the probe to the landing page, with `--follow-redirects`. */
+  redirects?: Array<{ url: string; status_code: number | null }>;
+}
+
+export interface DetectionReport {
+  exitCode: number;
+  status: "portal_found" | "no_portal" | "network_not_ready" | "open_failed" | "error";
+  portal_url?: string | null;
+  found_by?: { stage: "captive_api" | "standard" | "gateway" | "dns"; endpoint: string } | null;
+  opened_with?: string | null;
+  attempts?: number;
+  wifi_reset?: boolean;
+  /** Reported by the network's Captive Portal API (RFC 8908), when available. */
+  seconds_remaining?: number;
+  bytes_remaining?: number;
+  verdict?: {
+    strategy: "any-portal" | "majority" | "all-must-pass";
+    captive: boolean;
+    /** Share of the weighed evidence that agrees with `captive`, in percent. */
+    confidence: number;
+    portal_url?: string;
+    evidence: Array<{
+      kind:
+        | "redirect"
+        | "authentication_required"
+        | "injected_page"
+        | "body_mismatch"
+        | "status_mismatch"
+        | "tls_anomaly"
+        | "dns_anomaly"
+        | "passed";
+      stage: "captive_api" | "standard" | "gateway" | "dns";
+      endpoint: string;
+      url: string;
+      portal_url?: string;
+    }>;
+  };
+  /** Per address family, with the gateway found for it. */
+  families?: Array<{
+    family: "ipv4" | "ipv6";
+    gateway?: string;
+    portal: boolean;
+    reachable: boolean;
+  }>;
+  probes?: Array<ProbeRecord>;
+  errors: Array<string>;
+}
+
+export declare function detect(args?: Array<string>): Promise<DetectionReport>;
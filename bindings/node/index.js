@@ -42,4 +42,33 @@ function run(args = []) {
   });
 }
 
-module.exports = { run };
+function detect(args = []) {
+  assertSupportedPlatform();
+
+  return new Promise((resolve, reject) => {
+    const child = spawn(resolveBinary(), ['--json', ...args], {
+      stdio: ['ignore', 'pipe', 'inherit'],
+    });
+
+    let stdout = '';
+    child.stdout.setEncoding('utf8');
+    child.stdout.on('data', (chunk) => {
+      stdout += chunk;
+    });
+
+    child.on('error', (err) => reject(err));
+    child.on('close', (code, signal) => {
+      if (signal) {
+        reject(new Error(`reauthfi terminated by signal: ${signal}`));
+        return;
+      }
+      try {
+        resolve({ exitCode: code, ...JSON.parse(stdout) });
+      } catch (err) {
+        reject(new Error(`reauthfi exited with code ${code} without a JSON report`));
+      }
+    });
+  });
+}
+
+module.exports = { run, detect };
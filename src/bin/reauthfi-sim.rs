@@ -0,0 +1,91 @@
+use std::process::ExitCode;
+use std::thread;
+
+use reauthfi::{PortalSim, SimPortal};
+
+const HELP: &str = "\
+reauthfi-sim - Local HTTP server that behaves like a captive portal
+
+Usage:
+  reauthfi-sim <portal> [options]
+
+Portals:
+  open                  No portal; every check passes
+  redirect              302 to the login page
+  meta-refresh          200 splash page with a meta refresh
+  511                   511 Network Authentication Required with a Link header
+  js-redirect           200 page redirecting with JavaScript
+  hang                  Accept connections and never answer
+  walled-garden         Redirect every host not given with --allow
+
+Options:
+      --port <port>     Port on 127.0.0.1 (default: any free port)
+      --allow <host>    Let this host through a walled garden; repeatable
+  -h, --help            Show this help
+";
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<(SimPortal, u16)>, String> {
+    let mut portal = None;
+    let mut allowed = Vec::new();
+    let mut port = 0;
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("{name} requires a value"))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--port" => {
+                let value = value("--port")?;
+                port = value
+                    .parse()
+                    .map_err(|_| format!("Invalid --port value: {value}"))?;
+            }
+            "--allow" => allowed.push(value("--allow")?),
+            _ if !arg.starts_with('-') && portal.is_none() => portal = Some(arg.parse()?),
+            _ => return Err(format!("Unknown argument: {arg}")),
+        }
+    }
+
+    match portal {
+        Some(SimPortal::WalledGarden(_)) => Ok(Some((SimPortal::WalledGarden(allowed), port))),
+        Some(portal) if allowed.is_empty() => Ok(Some((portal, port))),
+        Some(portal) => Err(format!(
+            "--allow only applies to walled-garden, not {portal}"
+        )),
+        None => Err("Missing portal".to_string()),
+    }
+}
+
+fn main() -> ExitCode {
+    let (portal, port) = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(parsed)) => parsed,
+        Ok(None) => {
+            println!("{HELP}");
+            return ExitCode::SUCCESS;
+        }
+        Err(msg) => {
+            eprintln!("{msg}");
+            eprintln!();
+            eprintln!("{HELP}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let sim = match PortalSim::bind(("127.0.0.1", port), portal.clone()) {
+        Ok(sim) => sim,
+        Err(e) => {
+            eprintln!("Failed to listen on port {port}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // The config goes to stdout on its own so it can be saved and passed to --config.
+    eprintln!("Simulating a {portal} portal on http://{}", sim.addr());
+    print!("{}", toml::to_string(&sim.settings()).unwrap_or_default());
+
+    loop {
+        thread::park();
+    }
+}
@@ -0,0 +1,599 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::report::{format_bytes, format_seconds};
+use crate::{
+    error_reason, report_probe, CommandRunner, DetectionConfig, DetectionContext, DetectionResult,
+    DetectionTarget, HttpClient, NetworkClient, Options, Outcome, ProbeStage, ReauthfiError,
+    ReportEvent, SystemCommandRunner,
+};
+
+/// Advertised instead of an API URI by networks without a captive portal (RFC 8910).
+const UNRESTRICTED: &str = "urn:ietf:params:capport:unrestricted";
+
+/// Where the DHCP lease carrying the Captive Portal API URI (option 114) is read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DhcpLeaseSource {
+    None,
+    /// `ipconfig getpacket <interface>` for the default route's interface (macOS).
+    Ipconfig,
+    /// The newest lease of the default route's interface in these directories
+    /// (NetworkManager, systemd-networkd, dhclient).
+    LeaseFiles(&'static [&'static str]),
+}
+
+/// The JSON served by a Captive Portal API (RFC 8908). Read with the RFC's
+/// kebab-case keys, written in snake_case like the rest of the JSON output.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub struct CaptivePortalStatus {
+    pub captive: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_portal_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub venue_info_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub can_extend_session: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seconds_remaining: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes_remaining: Option<u64>,
+}
+
+impl CaptivePortalStatus {
+    pub fn parse(json: &str) -> Option<Self> {
+        serde_json::from_str(json).ok()
+    }
+
+    /// Whether the network told us when the session runs out.
+    pub fn has_session_limits(&self) -> bool {
+        self.seconds_remaining.is_some() || self.bytes_remaining.is_some()
+    }
+}
+
+/// The Captive Portal API URI from the current DHCP lease, if the network advertises one.
+///
+/// RFC 8908 only allows HTTPS APIs. A plain-HTTP URI, e.g. from a spoofed DHCP
+/// offer, is ignored rather than trusted over the probes.
+pub fn captive_api_uri(config: &DetectionConfig, commands: &dyn CommandRunner) -> Option<String> {
+    advertised_uri(config, commands)
+        .filter(|uri| uri == UNRESTRICTED || uri.to_ascii_lowercase().starts_with("https://"))
+}
+
+fn advertised_uri(config: &DetectionConfig, commands: &dyn CommandRunner) -> Option<String> {
+    match config.dhcp_leases {
+        DhcpLeaseSource::None => None,
+        DhcpLeaseSource::Ipconfig => {
+            let interface = route_interface(config, commands)?;
+            let packet = commands
+                .run(&["ipconfig", "getpacket", interface.as_str()])
+                .ok()?;
+            parse_ipconfig_packet(&packet)
+        }
+        DhcpLeaseSource::LeaseFiles(dirs) => {
+            let interface = route_interface(config, commands);
+            let path = current_lease_file(dirs, interface.as_deref())?;
+            parse_lease_file(&std::fs::read_to_string(path).ok()?)
+        }
+    }
+}
+
+/// The interface carrying the default route, from `route get` (`interface: en0`)
+/// or `ip route` (`dev wlan0`).
+fn route_interface(config: &DetectionConfig, commands: &dyn CommandRunner) -> Option<String> {
+    let route = commands.run(config.gateway_command).ok()?;
+    Regex::new(r"(?:interface:|\bdev)\s+(\S+)")
+        .ok()?
+        .captures(&route)
+        .map(|caps| caps[1].to_string())
+}
+
+/// The newest lease of `interface`, or the newest lease at all when the interface
+/// is unknown. Older leases belong to networks joined before, whose API is gone.
+fn current_lease_file(dirs: &[&str], interface: Option<&str>) -> Option<PathBuf> {
+    let index = interface.and_then(crate::family::interface_index);
+    dirs.iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let meta = entry.metadata().ok()?;
+            meta.is_file()
+                .then(|| Some((meta.modified().ok()?, entry.path())))
+                .flatten()
+        })
+        .filter(|(_, path)| match interface {
+            Some(interface) => is_lease_of(path, interface, index),
+            None => true,
+        })
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path)
+}
+
+/// NetworkManager and dhclient put the interface name in the file name
+/// (`internal-<uuid>-wlan0.lease`, `dhclient.wlan0.leases`); systemd-networkd
+/// names leases after the interface index.
+fn is_lease_of(path: &Path, interface: &str, index: Option<u32>) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    name.split(['-', '.']).any(|part| part == interface)
+        || index.is_some_and(|index| name == index.to_string())
+}
+
+/// Finds option 114 in `ipconfig getpacket` output. Newer macOS names it and prints
+/// a string; older releases print `option_114 (opaque)` followed by a hex dump.
+fn parse_ipconfig_packet(packet: &str) -> Option<String> {
+    let mut lines = packet.lines();
+    while let Some(line) = lines.next() {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let name = name.to_ascii_lowercase();
+        if !(name.starts_with("captive_portal") || name.starts_with("option_114")) {
+            continue;
+        }
+
+        let value = value.trim();
+        if !value.is_empty() {
+            return Some(value.to_string());
+        }
+
+        let bytes: Vec<u8> = lines
+            .clone()
+            .take_while(|l| is_hex_dump_line(l))
+            .flat_map(|l| {
+                l.split_whitespace()
+                    .skip(1)
+                    .take_while(|t| t.len() == 2)
+                    .filter_map(|t| u8::from_str_radix(t, 16).ok())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        return String::from_utf8(bytes).ok().filter(|uri| !uri.is_empty());
+    }
+    None
+}
+
+fn is_hex_dump_line(line: &str) -> bool {
+    line.split_whitespace()
+        .next()
+        .is_some_and(|offset| offset.len() == 4 && offset.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Reads option 114 from a lease file: `CAPTIVE_PORTAL=` in NetworkManager and
+/// systemd-networkd leases, `option default-url` in dhclient ones (last lease wins).
+fn parse_lease_file(text: &str) -> Option<String> {
+    let key_value = Regex::new(r"(?m)^CAPTIVE_PORTAL=(.+)$").ok()?;
+    if let Some(caps) = key_value.captures(text) {
+        return Some(caps[1].trim().to_string());
+    }
+
+    let dhclient =
+        Regex::new(r#"option\s+(?:default-url|captive-portal|unknown-114)\s+"([^"]+)";"#).ok()?;
+    dhclient
+        .captures_iter(text)
+        .last()
+        .map(|caps| caps[1].to_string())
+}
+
+const API_NAME: &str = "Captive API";
+
+/// The media type of the API document (RFC 8908, section 5).
+const API_MEDIA_TYPE: &str = "application/captive+json";
+
+/// Fetches and parses the API document. Failures are returned as the probe outcome
+/// to report; the status code is `None` when no response arrived.
+fn fetch_status(
+    net: &dyn NetworkClient,
+    uri: &str,
+    timeout: Duration,
+) -> (Option<u16>, Result<CaptivePortalStatus, Outcome>) {
+    match net.get_with_headers(uri, &[("accept", API_MEDIA_TYPE)], timeout) {
+        Ok(response) if !response.is_success() => {
+            let code = response.status();
+            (Some(code), Err(Outcome::Mismatch(code)))
+        }
+        Ok(response) => {
+            let code = response.status();
+            let status = response
+                .text()
+                .ok()
+                .and_then(|body| CaptivePortalStatus::parse(&body))
+                .ok_or_else(|| Outcome::Issue(format!("{}: invalid JSON", API_NAME)));
+            (Some(code), status)
+        }
+        Err(e) => (
+            None,
+            Err(Outcome::Issue(error_reason(API_NAME, &e, timeout))),
+        ),
+    }
+}
+
+/// Asks the Captive Portal API advertised via DHCP (RFC 8910, RFC 8908).
+///
+/// Returns `None` when no API is advertised or it does not give an answer, leaving
+/// the decision to the probing steps.
+pub fn detect_captive_api(ctx: &DetectionContext) -> Option<DetectionResult> {
+    let uri = captive_api_uri(ctx.config, ctx.commands)?;
+    if uri == UNRESTRICTED {
+        return Some(DetectionResult::NoPortalDetected);
+    }
+
+    ctx.reporter.report(&ReportEvent::StageStarted {
+        stage: ProbeStage::CaptiveApi,
+        endpoints: 1,
+    });
+    let target = DetectionTarget {
+        stage: ProbeStage::CaptiveApi,
+        family: None,
+        name: API_NAME.to_string(),
+        url: uri,
+        expected_status: Some(200),
+        expected_body: None,
+        allow_page_redirect: false,
+        nonexistent: false,
+    };
+    ctx.reporter.report(&ReportEvent::ProbeStarted {
+        stage: target.stage,
+        family: target.family,
+        endpoint: &target.name,
+        url: &target.url,
+    });
+
+    let timeout = Duration::from_secs(ctx.options.timeout);
+    let started = Instant::now();
+    let (status_code, fetched) = fetch_status(ctx.net.as_ref(), &target.url, timeout);
+    let outcome = match &fetched {
+        Ok(CaptivePortalStatus { captive: false, .. }) => Outcome::ExpectedOk,
+        Ok(CaptivePortalStatus {
+            user_portal_url: Some(url),
+            ..
+        }) => Outcome::Portal(url.clone()),
+        Ok(_) => Outcome::Issue(format!("{}: no user-portal-url", API_NAME)),
+        Err(outcome) => outcome.clone(),
+    };
+    report_probe(ctx, &target, status_code, &outcome, started.elapsed());
+
+    match (outcome, fetched) {
+        (Outcome::Portal(url), _) => Some(DetectionResult::PortalFound(url)),
+        (Outcome::ExpectedOk, Ok(status)) if status.has_session_limits() => {
+            Some(DetectionResult::SessionActive(status))
+        }
+        (Outcome::ExpectedOk, _) => Some(DetectionResult::NoPortalDetected),
+        _ => None,
+    }
+}
+
+/// What `reauthfi status` shows: the advertised API and its answer.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CaptiveApiStatus {
+    /// `None` when the network does not advertise a Captive Portal API.
+    pub api_uri: Option<String>,
+    #[serde(flatten)]
+    pub status: Option<CaptivePortalStatus>,
+}
+
+impl fmt::Display for CaptiveApiStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (Some(uri), Some(status)) = (&self.api_uri, &self.status) else {
+            return writeln!(
+                f,
+                "This network does not advertise a Captive Portal API (RFC 8910)"
+            );
+        };
+
+        writeln!(f, "Captive Portal API: {}", uri)?;
+        writeln!(
+            f,
+            "Captive:            {}",
+            if status.captive { "yes" } else { "no" }
+        )?;
+        if let Some(url) = &status.user_portal_url {
+            writeln!(f, "Login page:         {}", url)?;
+        }
+        if let Some(url) = &status.venue_info_url {
+            writeln!(f, "Venue info:         {}", url)?;
+        }
+        if let Some(secs) = status.seconds_remaining {
+            writeln!(f, "Time remaining:     {}", format_seconds(secs))?;
+        }
+        if let Some(bytes) = status.bytes_remaining {
+            writeln!(f, "Data remaining:     {}", format_bytes(bytes))?;
+        }
+        if let Some(extend) = status.can_extend_session {
+            writeln!(
+                f,
+                "Can extend session: {}",
+                if extend { "yes" } else { "no" }
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Looks up and queries the Captive Portal API of the current network.
+pub fn query_captive_api(
+    config: &DetectionConfig,
+    net: &dyn NetworkClient,
+    commands: &dyn CommandRunner,
+    timeout: Duration,
+) -> Result<CaptiveApiStatus, ReauthfiError> {
+    let Some(uri) = captive_api_uri(config, commands) else {
+        return Ok(CaptiveApiStatus::default());
+    };
+    if uri == UNRESTRICTED {
+        return Ok(CaptiveApiStatus {
+            api_uri: Some(uri),
+            status: Some(CaptivePortalStatus::default()),
+        });
+    }
+
+    match fetch_status(net, &uri, timeout).1 {
+        Ok(status) => Ok(CaptiveApiStatus {
+            api_uri: Some(uri),
+            status: Some(status),
+        }),
+        Err(Outcome::Mismatch(code)) => Err(ReauthfiError::Network(format!(
+            "{}: status {}",
+            API_NAME, code
+        ))),
+        Err(Outcome::Issue(msg)) => Err(ReauthfiError::Network(msg)),
+        Err(_) => Err(ReauthfiError::Network(format!("{}: no answer", API_NAME))),
+    }
+}
+
+/// [`query_captive_api`] with the system network stack and commands.
+pub fn captive_api_status(
+    config: &DetectionConfig,
+    options: &Options,
+) -> Result<CaptiveApiStatus, ReauthfiError> {
+    let net = HttpClient::new(options.timeout)?;
+    query_captive_api(
+        config,
+        &net,
+        &SystemCommandRunner,
+        Duration::from_secs(options.timeout),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::testing::{fake_context, probe_config, ScriptedRunner};
+    use crate::{FakeNetworkClient, Options, SilentReporter};
+
+    const ROUTE: &str = "   route to: default\n    gateway: 10.0.0.1\n  interface: en0\n";
+    const API: &str = "https://capport.example/api";
+
+    fn detect(packet: &'static str, body: &'static str) -> (Option<DetectionResult>, Vec<String>) {
+        let config = probe_config()
+            .to_builder()
+            .dhcp_leases(DhcpLeaseSource::Ipconfig)
+            .build();
+        let commands = ScriptedRunner::new(&[
+            ("route -n get default", Ok(ROUTE)),
+            ("ipconfig getpacket en0", Ok(packet)),
+        ]);
+        let net = Arc::new(FakeNetworkClient::new().page(API, 200, body));
+        let options = Options::default();
+        let ctx = fake_context::<FakeNetworkClient>(
+            &config,
+            net.clone(),
+            &commands,
+            &options,
+            &SilentReporter,
+        );
+        (detect_captive_api(&ctx), net.requests())
+    }
+
+    #[test]
+    fn api_portal_url_is_authoritative() {
+        let (result, requested) = detect(
+            "captive_portal_URL (string): https://capport.example/api\n",
+            r#"{"captive": true, "user-portal-url": "https://login.example/portal",
+                "venue-info-url": "https://venue.example", "seconds-remaining": 0}"#,
+        );
+
+        assert_eq!(
+            result,
+            Some(DetectionResult::PortalFound(
+                "https://login.example/portal".to_string()
+            ))
+        );
+        assert_eq!(requested, vec![API]);
+    }
+
+    #[test]
+    fn api_is_asked_for_captive_json() {
+        let config = probe_config()
+            .to_builder()
+            .dhcp_leases(DhcpLeaseSource::Ipconfig)
+            .build();
+        let commands = ScriptedRunner::new(&[
+            ("route -n get default", Ok(ROUTE)),
+            (
+                "ipconfig getpacket en0",
+                Ok("captive_portal_URL (string): https://capport.example/api\n"),
+            ),
+        ]);
+        let net = FakeNetworkClient::new().page(API, 200, r#"{"captive": false}"#);
+
+        query_captive_api(&config, &net, &commands, Duration::from_secs(1)).unwrap();
+        assert_eq!(
+            net.headers_sent(API),
+            vec![("accept".to_string(), API_MEDIA_TYPE.to_string())]
+        );
+    }
+
+    #[test]
+    fn plain_http_api_is_not_trusted() {
+        let (result, requested) = detect(
+            "captive_portal_URL (string): http://capport.example/api\n",
+            r#"{"captive": true, "user-portal-url": "http://evil.example/"}"#,
+        );
+        assert_eq!(result, None);
+        assert!(requested.is_empty());
+    }
+
+    #[test]
+    fn api_saying_not_captive_means_no_portal() {
+        let (result, _) = detect(
+            "option_114 (string): https://capport.example/api\n",
+            r#"{"captive": false}"#,
+        );
+        assert_eq!(result, Some(DetectionResult::NoPortalDetected));
+    }
+
+    #[test]
+    fn session_limits_are_surfaced() {
+        let (result, _) = detect(
+            "captive_portal_URL (string): https://capport.example/api\n",
+            r#"{"captive": false, "seconds-remaining": 326, "bytes-remaining": 65536,
+                "can-extend-session": true}"#,
+        );
+
+        let Some(DetectionResult::SessionActive(status)) = result else {
+            panic!("expected an active session, got {:?}", result);
+        };
+        assert_eq!(status.seconds_remaining, Some(326));
+        assert_eq!(status.bytes_remaining, Some(65536));
+        let json = serde_json::to_value(&status).unwrap();
+        assert_eq!(json["seconds_remaining"], 326);
+    }
+
+    #[test]
+    fn status_describes_the_session() {
+        let config = probe_config()
+            .to_builder()
+            .dhcp_leases(DhcpLeaseSource::Ipconfig)
+            .build();
+        let commands = ScriptedRunner::new(&[
+            ("route -n get default", Ok(ROUTE)),
+            (
+                "ipconfig getpacket en0",
+                Ok("captive_portal_URL (string): https://capport.example/api\n"),
+            ),
+        ]);
+        let net = FakeNetworkClient::new().page(
+            API,
+            200,
+            r#"{"captive": false, "user-portal-url": "https://login.example/",
+                "seconds-remaining": 3725, "bytes-remaining": 1572864}"#,
+        );
+
+        let status = query_captive_api(&config, &net, &commands, Duration::from_secs(1)).unwrap();
+        let text = status.to_string();
+        assert!(text.contains("Captive:            no"));
+        assert!(text.contains("Time remaining:     1h 2m"));
+        assert!(text.contains("Data remaining:     1.5 MB"));
+
+        let json = serde_json::to_value(&status).unwrap();
+        assert_eq!(json["api_uri"], API);
+        assert_eq!(json["bytes_remaining"], 1572864);
+    }
+
+    #[test]
+    fn unusable_api_defers_to_probes() {
+        let (result, _) = detect(
+            "captive_portal_URL (string): https://capport.example/api\n",
+            "<html>not json</html>",
+        );
+        assert_eq!(result, None);
+
+        let (result, requested) = detect("dhcp_message_type (uint8): ACK 0x5\n", "");
+        assert_eq!(result, None);
+        assert!(requested.is_empty());
+    }
+
+    #[test]
+    fn unrestricted_uri_skips_the_request() {
+        let (result, requested) = detect(
+            "captive_portal_URL (string): urn:ietf:params:capport:unrestricted\n",
+            "",
+        );
+        assert_eq!(result, Some(DetectionResult::NoPortalDetected));
+        assert!(requested.is_empty());
+    }
+
+    #[test]
+    fn ipconfig_hex_dump_is_decoded() {
+        let packet = "server_identifier (ip): 10.0.0.1\n\
+                      option_114 (opaque):\n\
+                      0000  68 74 74 70 73 3a 2f 2f  63 2e 65 78 2f 61 70 69  https://c.ex/api\n\
+                      end (none):\n";
+        assert_eq!(
+            parse_ipconfig_packet(packet).as_deref(),
+            Some("https://c.ex/api")
+        );
+    }
+
+    #[test]
+    fn lease_files_are_parsed() {
+        let networkd =
+            "ADDRESS=10.0.0.23\nROUTER=10.0.0.1\nCAPTIVE_PORTAL=https://capport.example/api\n";
+        assert_eq!(parse_lease_file(networkd).as_deref(), Some(API));
+
+        let dhclient = r#"
+lease {
+  interface "wlan0";
+  option default-url "https://old.example/api";
+}
+lease {
+  interface "wlan0";
+  option default-url "https://capport.example/api";
+}
+"#;
+        assert_eq!(parse_lease_file(dhclient).as_deref(), Some(API));
+        assert_eq!(parse_lease_file("ADDRESS=10.0.0.23\n"), None);
+    }
+
+    #[test]
+    fn only_the_current_lease_is_read() {
+        let dir = std::env::temp_dir().join(format!("reauthfi-leases-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, text: &str, age: u64| {
+            let path = dir.join(name);
+            std::fs::write(&path, text).unwrap();
+            let modified = std::time::SystemTime::now() - Duration::from_secs(age);
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        };
+        // A network joined earlier advertised an API; the current one does not.
+        write(
+            "internal-1111-wlan0.lease",
+            "CAPTIVE_PORTAL=https://old.example/api\n",
+            60,
+        );
+        write("internal-2222-eth0.lease", "ADDRESS=10.0.0.23\n", 30);
+        write("internal-3333-wlan0.lease", "ADDRESS=10.0.0.23\n", 10);
+        let dirs: &[&str] = &[dir.to_str().unwrap()];
+
+        let newest = |interface| {
+            current_lease_file(dirs, interface)
+                .and_then(|path| path.file_name()?.to_str().map(str::to_string))
+        };
+        assert_eq!(
+            newest(Some("wlan0")).as_deref(),
+            Some("internal-3333-wlan0.lease")
+        );
+        assert_eq!(
+            newest(Some("eth0")).as_deref(),
+            Some("internal-2222-eth0.lease")
+        );
+        assert_eq!(newest(Some("wlan1")), None);
+        assert_eq!(newest(None).as_deref(), Some("internal-3333-wlan0.lease"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
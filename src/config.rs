@@ -0,0 +1,564 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    detection_config, identify_network, BodyMatch, CommandRunner, DetectionConfig,
+    DetectionEndpoint, Options, ReauthfiError, SystemCommandRunner, VerdictStrategy,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct EndpointSetting {
+    pub name: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_status: Option<u16>,
+    /// `"exact page"` or `{ regex = "..." }`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_body: Option<BodyMatch>,
+}
+
+/// One layer of settings. The top level of the file, each network profile and
+/// the command line are all layers; later layers win.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wifi_reset: Option<bool>,
+    /// Requests spent following a portal's redirects; 0 keeps the first `Location`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub follow_redirects: Option<u32>,
+    /// `any-portal`, `majority` or `all-must-pass`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verdict: Option<VerdictStrategy>,
+    /// Resolve the probe hosts to spot portals that hijack DNS.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns_check: Option<bool>,
+    /// Treat any answer for a made-up name as a hijack.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nxdomain_check: Option<bool>,
+    /// Command used to open the portal; `%s` is replaced by the URL, otherwise
+    /// the URL is appended.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opener: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub endpoints: Vec<EndpointSetting>,
+    /// Names of built-in endpoints to skip, e.g. `["Google"]`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disable_endpoints: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gateway_paths: Vec<String>,
+    /// Probed instead of the default gateway, e.g. `"127.0.0.1:8080"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gateway: Option<String>,
+    /// Seconds before a session reported by the Captive Portal API ends that
+    /// watch mode opens its portal.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry_margin: Option<u64>,
+}
+
+impl Settings {
+    /// Rejects values the command line would reject too.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.timeout == Some(0) {
+            return Err("timeout must be at least 1 second".to_string());
+        }
+        if self.expiry_margin == Some(0) {
+            return Err("expiry_margin must be at least 1 second".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, config: DetectionConfig, options: &mut Options) -> DetectionConfig {
+        if let Some(timeout) = self.timeout {
+            options.timeout = timeout;
+        }
+        if let Some(wifi_reset) = self.wifi_reset {
+            options.wifi_reset = wifi_reset;
+        }
+        if let Some(follow_redirects) = self.follow_redirects {
+            options.follow_redirects = follow_redirects;
+        }
+        if let Some(verdict) = self.verdict {
+            options.verdict = verdict;
+        }
+        if let Some(dns_check) = self.dns_check {
+            options.dns_check = dns_check;
+        }
+        if let Some(nxdomain_check) = self.nxdomain_check {
+            options.nxdomain_check = nxdomain_check;
+        }
+        if let Some(opener) = &self.opener {
+            options.opener = Some(opener.clone());
+        }
+
+        let mut builder = config.to_builder();
+        for name in &self.disable_endpoints {
+            builder = builder.without_endpoint(name);
+        }
+        for endpoint in &self.endpoints {
+            let mut detection_endpoint = DetectionEndpoint::new(
+                endpoint.name.clone(),
+                endpoint.url.clone(),
+                endpoint.expected_status,
+            );
+            detection_endpoint.expected_body = endpoint.expected_body.clone();
+            builder = builder.endpoint(detection_endpoint);
+        }
+        for path in &self.gateway_paths {
+            builder = builder.gateway_endpoint(path.clone());
+        }
+        if let Some(gateway) = &self.gateway {
+            builder = builder.gateway_address(gateway.clone());
+        }
+        builder.build()
+    }
+}
+
+/// `~/.config/reauthfi/config.toml`.
+///
+/// ```toml
+/// timeout = 8
+/// disable_endpoints = ["Google"]
+///
+/// [[endpoints]]
+/// name = "Corp"
+/// url = "http://check.corp.example/generate_204"
+/// expected_status = 204
+///
+/// [networks."Hotel Guest"]   # SSID or gateway IP
+/// gateway_paths = ["/login.html"]
+/// wifi_reset = false
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    #[serde(flatten)]
+    pub defaults: Settings,
+    #[serde(default)]
+    pub networks: BTreeMap<String, Settings>,
+}
+
+impl ConfigFile {
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .map(|dir| dir.join("reauthfi").join("config.toml"))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ReauthfiError> {
+        Self::from_toml(text).map_err(|e| ReauthfiError::Setup(format!("invalid config: {}", e)))
+    }
+
+    /// Loads `path`; a missing file is the same as an empty one.
+    pub fn load(path: &Path) -> Result<Self, ReauthfiError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::from_toml(&text).map_err(|e| {
+                ReauthfiError::Setup(format!("invalid config {}: {}", path.display(), e))
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn from_toml(text: &str) -> Result<Self, String> {
+        let file: Self = toml::from_str(text).map_err(|e| e.to_string())?;
+        file.defaults.validate()?;
+        for (name, settings) in &file.networks {
+            settings
+                .validate()
+                .map_err(|e| format!("[networks.\"{}\"] {}", name, e))?;
+        }
+        Ok(file)
+    }
+
+    /// Names of the network profiles matching the current SSID or gateway, SSID first.
+    pub fn matching_profiles(&self, network: &NetworkIdentity) -> Vec<&str> {
+        [network.ssid.as_deref(), network.gateway.as_deref()]
+            .into_iter()
+            .flatten()
+            .filter_map(|key| self.networks.get_key_value(key).map(|(k, _)| k.as_str()))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct NetworkIdentity {
+    pub ssid: Option<String>,
+    pub gateway: Option<String>,
+}
+
+/// The result of layering the built-in preset, the config file, matching
+/// network profiles and command-line flags.
+#[derive(Debug, Clone)]
+pub struct EffectiveConfig {
+    pub config: DetectionConfig,
+    pub options: Options,
+    pub source: Option<PathBuf>,
+    pub network: NetworkIdentity,
+    pub profiles: Vec<String>,
+    /// For [`WatchOptions::expiry_margin`](crate::WatchOptions::expiry_margin), when set.
+    pub expiry_margin: Option<Duration>,
+}
+
+impl EffectiveConfig {
+    /// Loads the config file (`path`, or the default location) and resolves it for
+    /// the network this machine is on. An explicitly given `path` must exist.
+    pub fn load(
+        path: Option<PathBuf>,
+        cli: &Settings,
+        options: Options,
+    ) -> Result<Self, ReauthfiError> {
+        ConfigLayers::load(path, cli, options)
+            .map(|layers| layers.resolve_current(&SystemCommandRunner))
+    }
+
+    pub fn resolve(
+        preset: &DetectionConfig,
+        file: &ConfigFile,
+        network: NetworkIdentity,
+        cli: &Settings,
+        mut options: Options,
+    ) -> Self {
+        let profiles: Vec<String> = file
+            .matching_profiles(&network)
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+
+        let mut config = file.defaults.apply(preset.clone(), &mut options);
+        for name in &profiles {
+            config = file.networks[name].apply(config, &mut options);
+        }
+        config = cli.apply(config, &mut options);
+        let expiry_margin = std::iter::once(&file.defaults)
+            .chain(profiles.iter().map(|name| &file.networks[name]))
+            .chain([cli])
+            .rev()
+            .find_map(|layer| layer.expiry_margin)
+            .map(Duration::from_secs);
+
+        Self {
+            config,
+            options,
+            source: None,
+            network,
+            profiles,
+            expiry_margin,
+        }
+    }
+
+    /// Renders the merged settings as TOML for `reauthfi config show`.
+    pub fn to_toml(&self) -> String {
+        #[derive(Serialize)]
+        struct Shown<'a> {
+            source: Option<String>,
+            network: &'a NetworkIdentity,
+            profiles: &'a [String],
+            timeout: u64,
+            wifi_reset: bool,
+            follow_redirects: u32,
+            verdict: VerdictStrategy,
+            dns_check: bool,
+            nxdomain_check: bool,
+            expiry_margin: Option<u64>,
+            opener: Option<&'a str>,
+            gateway: Option<&'a str>,
+            gateway_paths: Vec<&'a str>,
+            endpoints: Vec<EndpointSetting>,
+        }
+
+        let shown = Shown {
+            source: self.source.as_ref().map(|p| p.display().to_string()),
+            network: &self.network,
+            profiles: &self.profiles,
+            timeout: self.options.timeout,
+            wifi_reset: self.options.wifi_reset,
+            follow_redirects: self.options.follow_redirects,
+            verdict: self.options.verdict,
+            dns_check: self.options.dns_check,
+            nxdomain_check: self.options.nxdomain_check,
+            expiry_margin: self.expiry_margin.map(|margin| margin.as_secs()),
+            opener: self.options.opener.as_deref(),
+            gateway: self.config.gateway_address.as_deref(),
+            gateway_paths: self
+                .config
+                .gateway_endpoints
+                .iter()
+                .map(|p| p.as_ref())
+                .collect(),
+            endpoints: self
+                .config
+                .detection_endpoints
+                .iter()
+                .map(|e| EndpointSetting {
+                    name: e.name.to_string(),
+                    url: e.url.to_string(),
+                    expected_status: e.expected_status,
+                    expected_body: e.expected_body.clone(),
+                })
+                .collect(),
+        };
+        toml::to_string(&shown).unwrap_or_default()
+    }
+}
+
+/// Everything [`EffectiveConfig`] is made of except the network, so commands that
+/// outlive a network (`watch`, `monitor`) can pick its profiles again.
+#[derive(Debug, Clone)]
+pub struct ConfigLayers {
+    pub preset: DetectionConfig,
+    pub file: ConfigFile,
+    pub cli: Settings,
+    pub options: Options,
+    pub source: Option<PathBuf>,
+}
+
+impl ConfigLayers {
+    /// Loads the config file (`path`, or the default location). An explicitly
+    /// given `path` must exist.
+    pub fn load(
+        path: Option<PathBuf>,
+        cli: &Settings,
+        options: Options,
+    ) -> Result<Self, ReauthfiError> {
+        let preset = detection_config()?;
+        if let Some(path) = &path {
+            if !path.exists() {
+                return Err(ReauthfiError::Setup(format!(
+                    "config file not found: {}",
+                    path.display()
+                )));
+            }
+        }
+
+        let path = path.or_else(ConfigFile::default_path);
+        let file = match &path {
+            Some(path) => ConfigFile::load(path)?,
+            None => ConfigFile::default(),
+        };
+        Ok(Self {
+            preset: preset.clone(),
+            file,
+            cli: cli.clone(),
+            options,
+            source: path.filter(|path| path.exists()),
+        })
+    }
+
+    /// Resolves the layers for the network `commands` find this machine on.
+    pub fn resolve_current(&self, commands: &dyn CommandRunner) -> EffectiveConfig {
+        // Looking up the SSID and gateway runs commands, so skip it when unused.
+        let network = if self.file.networks.is_empty() {
+            NetworkIdentity::default()
+        } else {
+            identify_network(&self.preset, commands)
+        };
+        self.resolve(network)
+    }
+
+    pub fn resolve(&self, network: NetworkIdentity) -> EffectiveConfig {
+        let mut effective = EffectiveConfig::resolve(
+            &self.preset,
+            &self.file,
+            network,
+            &self.cli,
+            self.options.clone(),
+        );
+        effective.source = self.source.clone();
+        effective
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MACOS_CONFIG;
+
+    const SAMPLE: &str = r#"
+timeout = 8
+disable_endpoints = ["Google"]
+opener = "firefox --new-window %s"
+verdict = "majority"
+
+[[endpoints]]
+name = "Corp"
+url = "http://check.corp.example/generate_204"
+expected_status = 204
+
+[[endpoints]]
+name = "Intranet"
+url = "http://intranet.corp.example/ping"
+expected_body = { regex = "^pong" }
+
+[networks."Hotel Guest"]
+timeout = 15
+gateway_paths = ["/login.html"]
+
+[networks."10.0.0.1"]
+wifi_reset = false
+"#;
+
+    fn identity(ssid: Option<&str>, gateway: Option<&str>) -> NetworkIdentity {
+        NetworkIdentity {
+            ssid: ssid.map(str::to_string),
+            gateway: gateway.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn misspelled_keys_are_rejected() {
+        for text in [
+            "timout = 8",
+            "wifi-reset = false",
+            "[networks.\"Cafe\"]\ntimout = 8",
+        ] {
+            let err = ConfigFile::parse(text).unwrap_err().to_string();
+            assert!(err.contains("unknown field"), "{text}: {err}");
+        }
+    }
+
+    #[test]
+    fn file_settings_apply_over_preset() {
+        let file = ConfigFile::parse(SAMPLE).unwrap();
+        let effective = EffectiveConfig::resolve(
+            &MACOS_CONFIG,
+            &file,
+            identity(Some("Cafe"), Some("192.168.1.1")),
+            &Settings::default(),
+            Options::default(),
+        );
+
+        assert!(effective.profiles.is_empty());
+        assert_eq!(effective.options.timeout, 8);
+        assert!(effective.options.wifi_reset);
+        assert_eq!(
+            effective.options.opener.as_deref(),
+            Some("firefox --new-window %s")
+        );
+        assert_eq!(effective.options.verdict, VerdictStrategy::Majority);
+        let names: Vec<&str> = effective
+            .config
+            .detection_endpoints
+            .iter()
+            .map(|e| e.name.as_ref())
+            .collect();
+        assert_eq!(names, vec!["Apple", "Corp", "Intranet"]);
+        assert_eq!(
+            effective.config.detection_endpoints[2].expected_body,
+            Some(BodyMatch::regex("^pong").unwrap())
+        );
+    }
+
+    #[test]
+    fn profiles_match_by_ssid_and_gateway() {
+        let file = ConfigFile::parse(SAMPLE).unwrap();
+        let effective = EffectiveConfig::resolve(
+            &MACOS_CONFIG,
+            &file,
+            identity(Some("Hotel Guest"), Some("10.0.0.1")),
+            &Settings::default(),
+            Options::default(),
+        );
+
+        assert_eq!(effective.profiles, vec!["Hotel Guest", "10.0.0.1"]);
+        assert_eq!(effective.options.timeout, 15);
+        assert!(!effective.options.wifi_reset);
+        assert_eq!(
+            effective.config.gateway_endpoints.as_ref(),
+            ["/", "/login.html"]
+        );
+    }
+
+    #[test]
+    fn cli_settings_take_precedence() {
+        let file = ConfigFile::parse(SAMPLE).unwrap();
+        let cli = Settings {
+            timeout: Some(3),
+            wifi_reset: Some(true),
+            follow_redirects: Some(4),
+            gateway: Some("127.0.0.1:8080".to_string()),
+            ..Settings::default()
+        };
+        let effective = EffectiveConfig::resolve(
+            &MACOS_CONFIG,
+            &file,
+            identity(Some("Hotel Guest"), Some("10.0.0.1")),
+            &cli,
+            Options::default(),
+        );
+
+        assert_eq!(effective.options.timeout, 3);
+        assert!(effective.options.wifi_reset);
+        assert_eq!(effective.options.follow_redirects, 4);
+        assert_eq!(
+            effective.config.gateway_address.as_deref(),
+            Some("127.0.0.1:8080")
+        );
+        assert!(effective.to_toml().contains("timeout = 3"));
+    }
+
+    #[test]
+    fn expiry_margin_is_layered_like_other_settings() {
+        let file = ConfigFile::parse(
+            "expiry_margin = 90\n[networks.\"Hotel Guest\"]\nexpiry_margin = 120\n",
+        )
+        .unwrap();
+        let resolve = |ssid, cli: &Settings| {
+            EffectiveConfig::resolve(
+                &MACOS_CONFIG,
+                &file,
+                identity(Some(ssid), None),
+                cli,
+                Options::default(),
+            )
+            .expiry_margin
+        };
+
+        assert_eq!(
+            resolve("Cafe", &Settings::default()),
+            Some(Duration::from_secs(90))
+        );
+        assert_eq!(
+            resolve("Hotel Guest", &Settings::default()),
+            Some(Duration::from_secs(120))
+        );
+        let cli = Settings {
+            expiry_margin: Some(30),
+            ..Settings::default()
+        };
+        assert_eq!(resolve("Hotel Guest", &cli), Some(Duration::from_secs(30)));
+        assert!(ConfigFile::parse("expiry_margin = 0").is_err());
+    }
+
+    #[test]
+    fn invalid_body_regex_is_a_setup_error() {
+        let text = "[[endpoints]]\nname = \"Corp\"\nurl = \"http://corp.test/\"\nexpected_body = { regex = \"(unclosed\" }\n";
+        let err = ConfigFile::parse(text).unwrap_err();
+        assert!(
+            matches!(&err, ReauthfiError::Setup(msg) if msg.contains("invalid regex \"(unclosed\"")),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn invalid_file_is_a_setup_error() {
+        let err = ConfigFile::parse("timeout = \"soon\"").unwrap_err();
+        assert!(matches!(err, ReauthfiError::Setup(msg) if msg.starts_with("invalid config")));
+    }
+
+    #[test]
+    fn zero_timeout_is_rejected_like_on_the_command_line() {
+        let err = ConfigFile::parse("timeout = 0").unwrap_err();
+        assert!(matches!(err, ReauthfiError::Setup(msg) if msg.contains("timeout must be")));
+
+        let err = ConfigFile::parse("[networks.\"Cafe\"]\ntimeout = 0").unwrap_err();
+        assert!(matches!(err, ReauthfiError::Setup(msg) if msg.contains("Cafe")));
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{
+    fetch, url_host, DetectionContext, DetectionTarget, EvidenceKind, NetworkClient, Outcome,
+    ProbeResult, ProbeStage, ReportEvent,
+};
+
+/// Looks up host names. Some portals leave HTTP alone and answer every DNS
+/// query with their own address instead, which only shows in the answers.
+pub trait Resolver: Send + Sync {
+    fn lookup(&self, name: &str) -> io::Result<Vec<IpAddr>>;
+}
+
+/// The operating system's resolver.
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn lookup(&self, name: &str) -> io::Result<Vec<IpAddr>> {
+        Ok((name, 0).to_socket_addrs()?.map(|addr| addr.ip()).collect())
+    }
+}
+
+/// A [`Resolver`] that answers from a table, for testing detection offline.
+///
+/// Names without answers do not exist, unless [`StubResolver::otherwise`] is
+/// set, which answers every other name the way a hijacking portal does.
+#[derive(Default)]
+pub struct StubResolver {
+    answers: HashMap<String, Vec<IpAddr>>,
+    fallback: Option<Vec<IpAddr>>,
+}
+
+impl StubResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn answer(mut self, name: &str, addrs: &[IpAddr]) -> Self {
+        self.answers
+            .insert(name.to_ascii_lowercase(), addrs.to_vec());
+        self
+    }
+
+    /// Answers names without an entry with `addrs`.
+    pub fn otherwise(mut self, addrs: &[IpAddr]) -> Self {
+        self.fallback = Some(addrs.to_vec());
+        self
+    }
+}
+
+impl Resolver for StubResolver {
+    fn lookup(&self, name: &str) -> io::Result<Vec<IpAddr>> {
+        self.answers
+            .get(&name.to_ascii_lowercase())
+            .or(self.fallback.as_ref())
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such name"))
+    }
+}
+
+/// Private (RFC 1918) and carrier-grade NAT (RFC 6598) space, where public
+/// probe hosts never live but portals do.
+fn is_portal_address(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_private() || in_cgnat(ip),
+        IpAddr::V6(_) => false,
+    }
+}
+
+fn in_cgnat(ip: &Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    a == 100 && (64..128).contains(&b)
+}
+
+/// A name under `.com` that nobody registered, so any answer for it is made up.
+fn nonexistent_name() -> String {
+    let mut bits = RandomState::new().build_hasher().finish();
+    let label: String = (0..12)
+        .map(|_| {
+            let letter = (b'a' + (bits % 26) as u8) as char;
+            bits /= 26;
+            letter
+        })
+        .collect();
+    format!("reauthfi-{}.com", label)
+}
+
+/// The lookups to check: each standard endpoint's host and, unless turned off,
+/// a made-up name. Empty when the client cannot resolve names or the check is
+/// turned off.
+pub(crate) fn dns_targets(ctx: &DetectionContext) -> Vec<DetectionTarget> {
+    if !ctx.options.dns_check || ctx.net.resolver().is_none() {
+        return Vec::new();
+    }
+
+    let mut names: Vec<String> = Vec::new();
+    for endpoint in ctx.config.detection_endpoints.iter() {
+        let Some(host) = reqwest::Url::parse(&endpoint.url)
+            .ok()
+            .and_then(|url| url.domain().map(str::to_string))
+        else {
+            continue;
+        };
+        if !names.contains(&host) {
+            names.push(host);
+        }
+    }
+    let made_up = ctx
+        .options
+        .nxdomain_check
+        .then(|| dns_target(nonexistent_name(), true));
+    let targets: Vec<DetectionTarget> = names
+        .into_iter()
+        .map(|name| dns_target(name, false))
+        .chain(made_up)
+        .collect();
+
+    ctx.reporter.report(&ReportEvent::StageStarted {
+        stage: ProbeStage::Dns,
+        endpoints: targets.len(),
+    });
+    targets
+}
+
+fn dns_target(name: String, nonexistent: bool) -> DetectionTarget {
+    DetectionTarget {
+        stage: ProbeStage::Dns,
+        family: None,
+        url: name.clone(),
+        name,
+        expected_status: None,
+        expected_body: None,
+        allow_page_redirect: true,
+        nonexistent,
+    }
+}
+
+/// Resolves the target's name, giving up after `timeout`. An answer in portal
+/// address space is a hijack, and so is any answer for a name that does not
+/// exist. A hijack is evidence on its own, and leads to a portal only when one
+/// answers at that address.
+pub(crate) fn check(
+    net: &dyn NetworkClient,
+    target: &DetectionTarget,
+    timeout: Duration,
+    redirect_limit: u32,
+    cancel: &AtomicBool,
+) -> Option<ProbeResult> {
+    if cancel.load(Ordering::Relaxed) {
+        return None;
+    }
+    let started = Instant::now();
+    let result = |outcome| {
+        Some(ProbeResult {
+            status_code: None,
+            outcome,
+            hops: Vec::new(),
+            evidence: None,
+            elapsed: started.elapsed(),
+        })
+    };
+    let Some(resolver) = net.resolver() else {
+        return result(Outcome::ExpectedOk);
+    };
+
+    let addrs = match lookup(resolver, &target.name, timeout) {
+        Ok(addrs) => addrs,
+        Err(_) if target.nonexistent => return result(Outcome::ExpectedOk),
+        Err(e) => {
+            return result(Outcome::Issue(format!(
+                "{}: lookup failed ({})",
+                target.name, e
+            )))
+        }
+    };
+    let hijacked = addrs
+        .iter()
+        .find(|ip| is_portal_address(ip))
+        .or_else(|| addrs.first().filter(|_| target.nonexistent));
+    let Some(ip) = hijacked else {
+        return result(Outcome::ExpectedOk);
+    };
+
+    let portal = DetectionTarget {
+        url: format!("http://{}/", url_host(&ip.to_string())),
+        ..target.clone()
+    };
+    let fetched = fetch(net, &portal, timeout, redirect_limit, cancel)?;
+    let outcome = match fetched.outcome {
+        Outcome::Portal(url) | Outcome::AuthenticationRequired(url) => Outcome::Portal(url),
+        _ => Outcome::Issue(format!(
+            "{}: resolves to {}, where no portal answers",
+            target.name, ip
+        )),
+    };
+    Some(ProbeResult {
+        status_code: fetched.status_code,
+        outcome,
+        hops: fetched.hops,
+        evidence: Some(EvidenceKind::DnsAnomaly),
+        elapsed: started.elapsed(),
+    })
+}
+
+/// Looks `name` up on a thread of its own, as the system resolver cannot be
+/// given a deadline; on networks that drop DNS it would hold up the whole run.
+/// A lookup still running after `timeout` is left to finish by itself.
+fn lookup(resolver: Arc<dyn Resolver>, name: &str, timeout: Duration) -> io::Result<Vec<IpAddr>> {
+    let (tx, rx) = mpsc::channel();
+    let name = name.to_string();
+    thread::spawn(move || tx.send(resolver.lookup(&name)));
+    rx.recv_timeout(timeout).unwrap_or_else(|_| {
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("no answer within {}s", timeout.as_secs()),
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn private_and_cgnat_addresses_belong_to_portals() {
+        let flagged = |ip: &str| is_portal_address(&ip.parse().unwrap());
+        assert!(flagged("10.1.2.3"));
+        assert!(flagged("172.16.0.1"));
+        assert!(flagged("192.168.1.1"));
+        assert!(flagged("100.64.0.1"));
+        assert!(flagged("100.127.255.254"));
+        assert!(!flagged("100.128.0.1"));
+        assert!(!flagged("172.32.0.1"));
+        assert!(!flagged("17.253.144.10"));
+        assert!(!flagged("2001:db8::1"));
+    }
+
+    #[test]
+    fn made_up_names_differ_between_runs() {
+        let name = nonexistent_name();
+        assert!(name.starts_with("reauthfi-") && name.ends_with(".com"));
+        assert_ne!(name, nonexistent_name());
+    }
+
+    #[test]
+    fn stub_answers_unknown_names_only_with_a_fallback() {
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let stub = StubResolver::new().answer("Portal.test", &[ip]);
+        assert_eq!(stub.lookup("portal.test").unwrap(), vec![ip]);
+        assert!(stub.lookup("other.test").is_err());
+
+        let stub = stub.otherwise(&[ip]);
+        assert_eq!(stub.lookup("other.test").unwrap(), vec![ip]);
+    }
+
+    struct SilentResolver;
+
+    impl Resolver for SilentResolver {
+        fn lookup(&self, _name: &str) -> io::Result<Vec<IpAddr>> {
+            thread::sleep(Duration::from_secs(5));
+            Err(io::Error::new(io::ErrorKind::TimedOut, "resolver gave up"))
+        }
+    }
+
+    #[test]
+    fn lookups_give_up_after_the_timeout() {
+        let started = Instant::now();
+        let error = lookup(
+            Arc::new(SilentResolver),
+            "probe.test",
+            Duration::from_millis(50),
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,493 @@
+use std::borrow::Cow;
+
+use regex::Regex;
+use serde::Serialize;
+
+/// Where in a page a candidate portal URL was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CandidateSource {
+    /// `<meta http-equiv="refresh" content="0; url=...">`
+    MetaRefresh,
+    /// `window.location = "..."`, `location.href = '...'`, `location.replace("...")`
+    JsRedirect,
+    /// `<form action="...">`
+    FormAction,
+    /// `<a href="...">`
+    Link,
+}
+
+impl CandidateSource {
+    /// Redirects send the browser on by themselves; forms and links need a click,
+    /// so a page can carry them without being a portal.
+    pub fn is_redirect(self) -> bool {
+        matches!(
+            self,
+            CandidateSource::MetaRefresh | CandidateSource::JsRedirect
+        )
+    }
+
+    fn base_score(self) -> u32 {
+        match self {
+            CandidateSource::MetaRefresh => 100,
+            CandidateSource::JsRedirect => 90,
+            CandidateSource::FormAction => 70,
+            CandidateSource::Link => 50,
+        }
+    }
+}
+
+/// A URL a portal page may lead to, with how likely it is the login page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PortalCandidate {
+    pub url: String,
+    pub source: CandidateSource,
+    pub score: u32,
+}
+
+/// Bonus for URLs that read like a login page, to pick the login form over a search box.
+const LOGIN_HINT_BONUS: u32 = 10;
+/// Links among several others only count when they look like a login page.
+const EXTRA_LINK_SCORE: u32 = 20;
+
+const LOGIN_HINTS: &[&str] = &[
+    "login", "logon", "signin", "auth", "portal", "captive", "splash", "guest", "hotspot",
+    "connect", "prelogin", "wifi",
+];
+
+/// Only the path and query count: links on a portal page often share its host,
+/// and a probe host like `captive.apple.com` would make every link look like one.
+fn looks_like_login(url: &str) -> bool {
+    let Ok(url) = reqwest::Url::parse(url) else {
+        return false;
+    };
+    let rest = format!("{}?{}", url.path(), url.query().unwrap_or("")).to_ascii_lowercase();
+    LOGIN_HINTS.iter().any(|hint| rest.contains(hint))
+}
+
+/// Every URL the HTML at `base` may send the browser to, best first. A URL found
+/// several ways is listed once, with its best score.
+pub fn portal_candidates(html: &str, base: &str) -> Vec<PortalCandidate> {
+    let html = strip_comments(html);
+    let mut found: Vec<(CandidateSource, String)> = Vec::new();
+    let mut links = Vec::new();
+
+    for (tag, attrs) in tags(&html) {
+        match tag.as_str() {
+            "meta" => {
+                let refresh = attr(&attrs, "content").and_then(refresh_target);
+                if let Some(url) = refresh.and_then(|target| page_url(base, target)) {
+                    found.push((CandidateSource::MetaRefresh, url));
+                }
+            }
+            "form" => {
+                if let Some(url) = attr(&attrs, "action").and_then(|a| page_url(base, a)) {
+                    found.push((CandidateSource::FormAction, url));
+                }
+            }
+            _ => {
+                if let Some(url) = attr(&attrs, "href").and_then(|h| page_url(base, h)) {
+                    if !links.contains(&url) {
+                        links.push(url);
+                    }
+                }
+            }
+        }
+    }
+    for url in js_redirects(&html, base) {
+        found.push((CandidateSource::JsRedirect, url));
+    }
+
+    let mut candidates: Vec<PortalCandidate> = found
+        .into_iter()
+        .map(|(source, url)| candidate(source, url, source.base_score()))
+        .collect();
+    let lone_link = links.len() == 1;
+    candidates.extend(links.into_iter().filter_map(|url| {
+        if lone_link {
+            Some(candidate(
+                CandidateSource::Link,
+                url,
+                CandidateSource::Link.base_score(),
+            ))
+        } else {
+            looks_like_login(&url).then(|| candidate(CandidateSource::Link, url, EXTRA_LINK_SCORE))
+        }
+    }));
+
+    candidates.sort_by_key(|c| std::cmp::Reverse(c.score));
+    let mut seen = Vec::new();
+    candidates.retain(|c| {
+        let first = !seen.contains(&c.url);
+        if first {
+            seen.push(c.url.clone());
+        }
+        first
+    });
+    candidates
+}
+
+fn candidate(source: CandidateSource, url: String, score: u32) -> PortalCandidate {
+    let score = if looks_like_login(&url) {
+        score + LOGIN_HINT_BONUS
+    } else {
+        score
+    };
+    PortalCandidate { url, source, score }
+}
+
+/// The login page a portal's HTML at `base` most likely leads to.
+pub fn extract_portal_url(html: &str, base: &str) -> Option<String> {
+    portal_candidates(html, base)
+        .into_iter()
+        .next()
+        .map(|c| c.url)
+}
+
+/// Like [`extract_portal_url`], but only for pages that redirect by themselves
+/// (meta refresh or JavaScript), for probes where a form or link proves nothing.
+pub fn extract_redirect_url(html: &str, base: &str) -> Option<String> {
+    portal_candidates(html, base)
+        .into_iter()
+        .find(|c| c.source.is_redirect())
+        .map(|c| c.url)
+}
+
+pub fn extract_meta_refresh(html: &str, base: &str) -> Option<String> {
+    portal_candidates(html, base)
+        .into_iter()
+        .find(|c| c.source == CandidateSource::MetaRefresh)
+        .map(|c| c.url)
+}
+
+/// Drops `<!-- ... -->` outside `<script>` elements. Inside them it is the old
+/// `<!-- ... //-->` wrapper that hid scripts from ancient browsers, so the
+/// script still runs.
+fn strip_comments(html: &str) -> Cow<'_, str> {
+    match Regex::new(r"(?is)(<script\b.*?</script\s*>)|<!--.*?-->") {
+        Ok(re) => re.replace_all(html, |caps: &regex::Captures| {
+            caps.get(1).map_or("", |script| script.as_str()).to_string()
+        }),
+        Err(_) => Cow::Borrowed(html),
+    }
+}
+
+/// The `<a>`, `<form>` and `<meta>` tags of a page with their attributes,
+/// names lowercased. Quoted values may contain `>`.
+fn tags(html: &str) -> Vec<(String, Vec<(String, String)>)> {
+    let Ok(tag_re) = Regex::new(r#"(?i)<(a|form|meta)\b((?:[^>"']|"[^"]*"|'[^']*')*)>"#) else {
+        return Vec::new();
+    };
+    let Ok(attr_re) = Regex::new(
+        r#"([A-Za-z_:][-A-Za-z0-9_:.]*)(?:\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+)))?"#,
+    ) else {
+        return Vec::new();
+    };
+
+    tag_re
+        .captures_iter(html)
+        .map(|caps| {
+            let attrs = attr_re
+                .captures_iter(&caps[2])
+                .map(|a| {
+                    let value = a.get(2).or_else(|| a.get(3)).or_else(|| a.get(4));
+                    (
+                        a[1].to_ascii_lowercase(),
+                        value.map_or("", |v| v.as_str()).to_string(),
+                    )
+                })
+                .collect();
+            (caps[1].to_ascii_lowercase(), attrs)
+        })
+        .collect()
+}
+
+fn attr<'a>(attrs: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attrs
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// The URL of a refresh `content` value: `0; url=/login`, `1;URL='...'` or `0, /login`.
+fn refresh_target(content: &str) -> Option<&str> {
+    let re = Regex::new(r#"(?i)^\s*\d+(?:\.\d*)?\s*[;,]\s*(?:url\s*=\s*)?['"]?([^'"]+)"#).ok()?;
+    let target = re.captures(content)?.get(1)?.as_str().trim();
+    (!target.is_empty()).then_some(target)
+}
+
+/// `window.location = "..."`, `location.href = '...'` or `location.replace("...")`
+/// with a literal URL, in document order.
+fn js_redirects(html: &str, base: &str) -> Vec<String> {
+    let Ok(re) = Regex::new(
+        r#"(?i)\blocation(?:\.href)?\s*(?:=\s*|\.(?:replace|assign)\s*\(\s*)["']([^"']+)["']"#,
+    ) else {
+        return Vec::new();
+    };
+    re.captures_iter(html)
+        .filter_map(|caps| page_url(base, &caps[1]))
+        .collect()
+}
+
+/// Decodes the HTML entities that show up in attribute values, e.g. `&amp;` in
+/// query strings. Unknown entities are kept as they are.
+pub fn decode_html_entities(text: &str) -> Cow<'_, str> {
+    if !text.contains('&') {
+        return Cow::Borrowed(text);
+    }
+
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| Some((decode_entity(&rest[1..end])?, end)));
+        match entity {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    Cow::Owned(decoded)
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ => {
+            let code = match name.strip_prefix('#')? {
+                hex if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16).ok()?,
+                dec => dec.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+/// Resolves a relative (`/login.html`) or protocol-relative (`//10.0.0.1/auth`)
+/// reference against the URL of the page it came from; absolute URLs are kept.
+pub fn resolve_url(base: &str, reference: &str) -> Option<String> {
+    let reference = reference.trim();
+    if reference.is_empty() || reference.starts_with('#') {
+        return None;
+    }
+    if reference.starts_with("http://") || reference.starts_with("https://") {
+        return Some(reference.to_string());
+    }
+
+    let joined = reqwest::Url::parse(base).ok()?.join(reference).ok()?;
+    matches!(joined.scheme(), "http" | "https").then(|| joined.to_string())
+}
+
+/// Decodes and resolves an attribute value taken from the page at `base`.
+fn page_url(base: &str, raw: &str) -> Option<String> {
+    resolve_url(base, &decode_html_entities(raw))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROBE: &str = "http://captive.apple.com/hotspot-detect.html";
+
+    /// Snapshots in `testdata/portals` and the login URL each one leads to.
+    const CORPUS: &[(&str, &str, Option<&str>)] = &[
+        (
+            "mikrotik_redirect",
+            include_str!("../testdata/portals/mikrotik_redirect.html"),
+            Some("http://10.5.50.1/login?dst=http%3A%2F%2Fconnectivitycheck.gstatic.com%2Fgenerate_204"),
+        ),
+        (
+            "mikrotik_login",
+            include_str!("../testdata/portals/mikrotik_login.html"),
+            Some("http://10.5.50.1/login"),
+        ),
+        (
+            "pfsense",
+            include_str!("../testdata/portals/pfsense.html"),
+            Some("http://192.0.2.1:8002/index.php?zone=guest&redirurl=http%3A%2F%2Fcaptive.apple.com%2F"),
+        ),
+        (
+            "unifi",
+            include_str!("../testdata/portals/unifi.html"),
+            Some("https://192.0.2.10:8843/guest/s/default/?ap=f0:9f:c2:00:00:01&id=02:00:00:00:00:01&t=1700000000&url=http://captive.apple.com%2fhotspot-detect.html&ssid=Guest"),
+        ),
+        (
+            "meraki",
+            include_str!("../testdata/portals/meraki.html"),
+            Some("https://n123.network-auth.com/splash/?mac=E0:55:3D:00:00:01&real_ip=192.0.2.55&client_ip=10.0.0.55&client_mac=02:00:00:00:00:02&vap=0&a=a1b2c3&b=12345&node_mac=E0:55:3D:00:00:01&inline=1&continue_url=http%3A%2F%2Fconnectivitycheck.gstatic.com%2Fgenerate_204"),
+        ),
+        (
+            "cisco_wlc",
+            include_str!("../testdata/portals/cisco_wlc.html"),
+            Some("https://192.0.2.1/login.html?redirect=http://captive.apple.com/hotspot-detect.html"),
+        ),
+        (
+            "opennds",
+            include_str!("../testdata/portals/opennds.html"),
+            Some("http://captive.apple.com/opennds_preauth/"),
+        ),
+        (
+            "hotel_link",
+            include_str!("../testdata/portals/hotel_link.html"),
+            Some("https://wifi.hotel.example/connect?mac=02:00:00:00:00:03&room="),
+        ),
+        (
+            "coova_chilli",
+            include_str!("../testdata/portals/coova_chilli.html"),
+            Some("http://10.1.0.1:3990/prelogin?userurl=http%3A%2F%2Fcaptive.apple.com%2F"),
+        ),
+        (
+            "legacy_hotspot",
+            include_str!("../testdata/portals/legacy_hotspot.html"),
+            Some("http://192.0.2.20/hotspot/login.php?orig=http%3A%2F%2Fcaptive.apple.com%2F"),
+        ),
+        (
+            "router_status",
+            include_str!("../testdata/portals/router_status.html"),
+            None,
+        ),
+    ];
+
+    #[test]
+    fn corpus_pages_lead_to_their_login_url() {
+        for (name, html, expected) in CORPUS {
+            assert_eq!(
+                extract_portal_url(html, PROBE).as_deref(),
+                *expected,
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn only_redirects_count_as_redirects() {
+        let redirecting: Vec<&str> = CORPUS
+            .iter()
+            .filter(|(_, html, _)| extract_redirect_url(html, PROBE).is_some())
+            .map(|(name, _, _)| *name)
+            .collect();
+        assert_eq!(
+            redirecting,
+            [
+                "mikrotik_redirect",
+                "unifi",
+                "meraki",
+                "cisco_wlc",
+                "coova_chilli",
+                "legacy_hotspot"
+            ]
+        );
+    }
+
+    #[test]
+    fn candidates_are_ranked_and_deduplicated() {
+        let html = include_str!("../testdata/portals/coova_chilli.html");
+        let candidates = portal_candidates(html, PROBE);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].source, CandidateSource::JsRedirect);
+
+        let html = r#"<form action="/search"></form><form action="/guest/login"></form>"#;
+        let sources: Vec<(String, u32)> = portal_candidates(html, "http://10.0.0.1/")
+            .into_iter()
+            .map(|c| (c.url, c.score))
+            .collect();
+        assert_eq!(
+            sources,
+            [
+                ("http://10.0.0.1/guest/login".to_string(), 80),
+                ("http://10.0.0.1/search".to_string(), 70),
+            ]
+        );
+    }
+
+    #[test]
+    fn commented_out_redirects_are_ignored() {
+        let html = r#"<!-- <meta http-equiv="refresh" content="0; url=http://old.example/"> -->
+            <a href="http://portal.example/">Continue</a>"#;
+        assert_eq!(
+            extract_portal_url(html, PROBE).as_deref(),
+            Some("http://portal.example/")
+        );
+    }
+
+    #[test]
+    fn js_redirect_forms_are_recognized() {
+        for html in [
+            r#"location = 'http://p.example/a';"#,
+            r#"window.location.replace("http://p.example/a")"#,
+            r#"document.location.assign( "http://p.example/a" )"#,
+            r#"top.location.href="http://p.example/a""#,
+        ] {
+            assert_eq!(
+                extract_redirect_url(html, "http://probe.test/").as_deref(),
+                Some("http://p.example/a"),
+                "{html}"
+            );
+        }
+        let comparison = r#"if (location.href == "http://p.example/a") {}"#;
+        assert_eq!(extract_redirect_url(comparison, "http://probe.test/"), None);
+    }
+
+    #[test]
+    fn relative_meta_refresh_is_resolved() {
+        let base = "http://10.0.0.1/";
+        let page = |url: &str| format!(r#"<meta http-equiv="refresh" content="0; url={url}">"#);
+
+        assert_eq!(
+            extract_meta_refresh(&page("/login.html"), base).as_deref(),
+            Some("http://10.0.0.1/login.html")
+        );
+        assert_eq!(
+            extract_meta_refresh(&page("//10.0.0.1:8080/auth"), base).as_deref(),
+            Some("http://10.0.0.1:8080/auth")
+        );
+        assert_eq!(
+            extract_meta_refresh(&page("/login?a=1&amp;b=2"), base).as_deref(),
+            Some("http://10.0.0.1/login?a=1&b=2")
+        );
+        assert_eq!(
+            extract_meta_refresh(&page("'https://portal.example/x'"), base).as_deref(),
+            Some("https://portal.example/x")
+        );
+        assert_eq!(
+            extract_meta_refresh(r#"<meta http-equiv="refresh" content="30">"#, base),
+            None
+        );
+    }
+
+    #[test]
+    fn relative_location_is_resolved() {
+        let base = "http://captive.apple.com/hotspot-detect.html";
+        assert_eq!(
+            resolve_url(base, "/portal?ap=1").as_deref(),
+            Some("http://captive.apple.com/portal?ap=1")
+        );
+        assert_eq!(
+            resolve_url(base, "//10.1.1.1/login").as_deref(),
+            Some("http://10.1.1.1/login")
+        );
+        assert_eq!(resolve_url(base, "javascript:void(0)"), None);
+    }
+
+    #[test]
+    fn html_entities_are_decoded() {
+        assert_eq!(
+            decode_html_entities("a=1&amp;b=&#50;&#x33;&unknown;&"),
+            "a=1&b=23&unknown;&"
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::{AddressFamily, NetworkClient, ProbeError, ProbeResponse, Resolver};
+
+type Answer = Result<ProbeResponse, ProbeError>;
+
+/// A [`NetworkClient`] that answers from a script instead of the network, for
+/// testing detection offline.
+///
+/// Answers queued for a URL are given in order, and the last one is repeated.
+/// URLs without answers get the fallback, a connect error unless set with
+/// [`FakeNetworkClient::otherwise`]. Every answer carries the requested URL.
+/// Answers given with [`FakeNetworkClient::respond_over`] or
+/// [`FakeNetworkClient::fail_over`] apply only to requests over that family.
+pub struct FakeNetworkClient {
+    answers: Mutex<HashMap<String, VecDeque<Answer>>>,
+    delays: HashMap<String, Duration>,
+    fallback: Option<u16>,
+    requests: Mutex<Vec<String>>,
+    headers: Mutex<HashMap<String, Vec<(String, String)>>>,
+    resolver: Option<Arc<dyn Resolver>>,
+}
+
+impl FakeNetworkClient {
+    pub fn new() -> Self {
+        Self {
+            answers: Mutex::new(HashMap::new()),
+            delays: HashMap::new(),
+            fallback: None,
+            requests: Mutex::new(Vec::new()),
+            headers: Mutex::new(HashMap::new()),
+            resolver: None,
+        }
+    }
+
+    pub fn respond(self, url: &str, response: ProbeResponse) -> Self {
+        self.push(url, Ok(response))
+    }
+
+    pub fn fail(self, url: &str, error: ProbeError) -> Self {
+        self.push(url, Err(error))
+    }
+
+    pub fn respond_over(self, family: AddressFamily, url: &str, response: ProbeResponse) -> Self {
+        self.push(&family_key(family, url), Ok(response))
+    }
+
+    pub fn fail_over(self, family: AddressFamily, url: &str, error: ProbeError) -> Self {
+        self.push(&family_key(family, url), Err(error))
+    }
+
+    /// Shorthand for a redirect to `location`.
+    pub fn redirect(self, url: &str, status: u16, location: &str) -> Self {
+        self.respond(
+            url,
+            ProbeResponse::new(status, url).header("location", location),
+        )
+    }
+
+    /// Shorthand for a page with `status` and `body`.
+    pub fn page(self, url: &str, status: u16, body: &str) -> Self {
+        self.respond(url, ProbeResponse::new(status, url).body(body))
+    }
+
+    /// Holds every answer for `url` back for `delay`, like a slow server.
+    pub fn delay(mut self, url: &str, delay: Duration) -> Self {
+        self.delays.insert(url.to_string(), delay);
+        self
+    }
+
+    /// Answers URLs without a script with an empty `status` response.
+    pub fn otherwise(mut self, status: u16) -> Self {
+        self.fallback = Some(status);
+        self
+    }
+
+    /// Resolves names with `resolver`, e.g. a [`StubResolver`](crate::StubResolver),
+    /// which turns on the DNS checks; without one they are skipped.
+    pub fn with_resolver(mut self, resolver: impl Resolver + 'static) -> Self {
+        self.resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Every URL requested so far, in order.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// The headers sent with the last request for `url`.
+    pub fn headers_sent(&self, url: &str) -> Vec<(String, String)> {
+        self.headers
+            .lock()
+            .unwrap()
+            .get(url)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn push(self, url: &str, answer: Answer) -> Self {
+        self.answers
+            .lock()
+            .unwrap()
+            .entry(url.to_string())
+            .or_default()
+            .push_back(answer);
+        self
+    }
+
+    fn answer(&self, key: &str) -> Option<Answer> {
+        let mut answers = self.answers.lock().unwrap();
+        let queue = answers.get_mut(key)?;
+        if queue.len() > 1 {
+            return queue.pop_front();
+        }
+        match queue.front()? {
+            Ok(response) => match response.replay() {
+                Some(copy) => Some(Ok(copy)),
+                None => queue.pop_front(),
+            },
+            Err(error) => Some(Err(error.clone())),
+        }
+    }
+}
+
+fn family_key(family: AddressFamily, url: &str) -> String {
+    format!("{} {}", family, url)
+}
+
+impl Default for FakeNetworkClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NetworkClient for FakeNetworkClient {
+    fn get(&self, url: &str, _timeout: Duration) -> Result<ProbeResponse, ProbeError> {
+        self.requests.lock().unwrap().push(url.to_string());
+        if let Some(delay) = self.delays.get(url) {
+            std::thread::sleep(*delay);
+        }
+        match self.answer(url) {
+            Some(answer) => answer.map(|response| response.with_url(url)),
+            None => match self.fallback {
+                Some(status) => Ok(ProbeResponse::new(status, url)),
+                None => Err(ProbeError::Connect),
+            },
+        }
+    }
+
+    fn get_over(
+        &self,
+        url: &str,
+        family: AddressFamily,
+        timeout: Duration,
+    ) -> Result<ProbeResponse, ProbeError> {
+        match self.answer(&family_key(family, url)) {
+            Some(answer) => {
+                self.requests.lock().unwrap().push(url.to_string());
+                answer.map(|response| response.with_url(url))
+            }
+            None => self.get(url, timeout),
+        }
+    }
+
+    fn get_with_headers(
+        &self,
+        url: &str,
+        headers: &[(&str, &str)],
+        timeout: Duration,
+    ) -> Result<ProbeResponse, ProbeError> {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        self.headers
+            .lock()
+            .unwrap()
+            .insert(url.to_string(), headers);
+        self.get(url, timeout)
+    }
+
+    fn resolver(&self) -> Option<Arc<dyn Resolver>> {
+        self.resolver.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn answers_are_given_in_order_and_the_last_repeats() {
+        let net = FakeNetworkClient::new()
+            .fail("http://a.test/", ProbeError::Timeout)
+            .page("http://a.test/", 200, "ok");
+
+        assert_eq!(
+            net.get("http://a.test/", TIMEOUT).unwrap_err(),
+            ProbeError::Timeout
+        );
+        for _ in 0..2 {
+            let response = net.get("http://a.test/", TIMEOUT).unwrap();
+            assert_eq!(response.text().unwrap(), "ok");
+        }
+        assert!(matches!(
+            net.get("http://b.test/", TIMEOUT),
+            Err(ProbeError::Connect)
+        ));
+        assert_eq!(net.requests().len(), 4);
+    }
+
+    #[test]
+    fn family_answers_apply_to_that_family_only() {
+        let net = FakeNetworkClient::new()
+            .page("http://a.test/", 204, "")
+            .fail_over(AddressFamily::Ipv6, "http://a.test/", ProbeError::Timeout);
+
+        let over = |family| net.get_over("http://a.test/", family, TIMEOUT);
+        assert_eq!(over(AddressFamily::Ipv4).unwrap().status(), 204);
+        assert_eq!(over(AddressFamily::Ipv6).unwrap_err(), ProbeError::Timeout);
+        assert_eq!(net.get("http://a.test/", TIMEOUT).unwrap().status(), 204);
+    }
+
+    #[test]
+    fn fallback_answers_unscripted_urls() {
+        let net = FakeNetworkClient::new().otherwise(204);
+        let response = net.get("http://b.test/", TIMEOUT).unwrap();
+        assert_eq!(response.status(), 204);
+        assert_eq!(response.url(), "http://b.test/");
+    }
+}
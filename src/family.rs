@@ -0,0 +1,181 @@
+use std::fmt;
+use std::net::{Ipv6Addr, SocketAddr, SocketAddrV6};
+
+use serde::Serialize;
+
+/// IPv4 or IPv6; dual-stack networks are probed over each separately, since a
+/// portal may intercept only one of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressFamily {
+    Ipv4,
+    Ipv6,
+}
+
+impl AddressFamily {
+    /// The family of a gateway address such as `10.0.0.1`, `fe80::1%en0` or
+    /// `[::1]:8080`; host names count as IPv4.
+    pub fn of_address(address: &str) -> Self {
+        if address.starts_with('[') || parse_scoped(address).is_some() {
+            AddressFamily::Ipv6
+        } else {
+            AddressFamily::Ipv4
+        }
+    }
+
+    pub(crate) fn includes(self, addr: &SocketAddr) -> bool {
+        match self {
+            AddressFamily::Ipv4 => addr.is_ipv4(),
+            AddressFamily::Ipv6 => addr.is_ipv6(),
+        }
+    }
+}
+
+impl fmt::Display for AddressFamily {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            AddressFamily::Ipv4 => "IPv4",
+            AddressFamily::Ipv6 => "IPv6",
+        })
+    }
+}
+
+/// `fe80::1%en0` as an address and its zone.
+fn parse_scoped(address: &str) -> Option<(Ipv6Addr, Option<&str>)> {
+    let (ip, zone) = match address.split_once('%') {
+        Some((ip, zone)) => (ip, Some(zone)),
+        None => (address, None),
+    };
+    Some((ip.parse().ok()?, zone.filter(|zone| !zone.is_empty())))
+}
+
+/// The host part of a URL for `address`: IPv6 in brackets with the zone ID
+/// percent-encoded (RFC 6874), e.g. `[fe80::1%25en0]`; anything else as given.
+pub fn url_host(address: &str) -> String {
+    match parse_scoped(address) {
+        Some((ip, Some(zone))) => format!("[{}%25{}]", ip, zone),
+        Some((ip, None)) => format!("[{}]", ip),
+        None => address.to_string(),
+    }
+}
+
+/// Host name standing in for a zoned IPv6 literal, which URL parsers reject.
+pub(crate) const STAND_IN: &str = "scoped-gateway.invalid";
+
+/// Where to connect for a URL whose host is a zoned IPv6 literal.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ScopedTarget {
+    /// The URL with [`STAND_IN`] as its host.
+    pub(crate) url: String,
+    /// The original host and port, e.g. `[fe80::1%25en0]:8080`, sent as `Host`.
+    pub(crate) authority: String,
+    pub(crate) addr: SocketAddr,
+    host: String,
+}
+
+impl ScopedTarget {
+    /// Puts the original host back where a URL still names the stand-in.
+    pub(crate) fn restore(&self, url: &str) -> String {
+        url.replace(STAND_IN, &self.host)
+    }
+}
+
+pub(crate) fn scoped_target(url: &str) -> Option<ScopedTarget> {
+    let start = url.find("://[")? + 4;
+    let end = start + url[start..].find(']')?;
+    let (ip, zone) = url[start..end].split_once("%25")?;
+    let ip: Ipv6Addr = ip.parse().ok()?;
+    let rest = &url[end + 1..];
+    let port = rest
+        .strip_prefix(':')
+        .map(|rest| rest.split(['/', '?', '#']).next().unwrap_or(rest));
+    let port_number = port.and_then(|port| port.parse().ok()).unwrap_or(80);
+
+    let addr = SocketAddrV6::new(ip, port_number, 0, interface_index(zone)?);
+    let host = url[start - 1..=end].to_string();
+    Some(ScopedTarget {
+        url: format!("{}{}{}", &url[..start - 1], STAND_IN, rest),
+        authority: match port {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.clone(),
+        },
+        addr: SocketAddr::V6(addr),
+        host,
+    })
+}
+
+/// The interface index for a zone ID, which is either the index itself or an
+/// interface name.
+pub(crate) fn interface_index(zone: &str) -> Option<u32> {
+    if let Ok(index) = zone.parse() {
+        return Some(index);
+    }
+    #[cfg(unix)]
+    {
+        let name = std::ffi::CString::new(zone).ok()?;
+        // SAFETY: `name` is a valid NUL-terminated string for the whole call.
+        let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        (index != 0).then_some(index)
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ipv6_hosts_are_bracketed_with_encoded_zones() {
+        assert_eq!(url_host("10.0.0.1"), "10.0.0.1");
+        assert_eq!(url_host("127.0.0.1:8080"), "127.0.0.1:8080");
+        assert_eq!(url_host("2001:db8::1"), "[2001:db8::1]");
+        assert_eq!(url_host("fe80::1%en0"), "[fe80::1%25en0]");
+        assert_eq!(url_host("[::1]:8080"), "[::1]:8080");
+    }
+
+    #[test]
+    fn families_are_told_apart_by_address() {
+        assert_eq!(AddressFamily::of_address("10.0.0.1"), AddressFamily::Ipv4);
+        assert_eq!(
+            AddressFamily::of_address("portal.test:80"),
+            AddressFamily::Ipv4
+        );
+        assert_eq!(
+            AddressFamily::of_address("fe80::1%wlan0"),
+            AddressFamily::Ipv6
+        );
+        assert_eq!(AddressFamily::of_address("[::1]:8080"), AddressFamily::Ipv6);
+    }
+
+    #[test]
+    fn zoned_urls_connect_through_a_stand_in_name() {
+        let target = scoped_target("http://[fe80::1%253]:8080/login?x=1").unwrap();
+        assert_eq!(target.url, "http://scoped-gateway.invalid:8080/login?x=1");
+        assert_eq!(target.authority, "[fe80::1%253]:8080");
+        assert_eq!(target.addr, "[fe80::1%3]:8080".parse().unwrap());
+
+        let target = scoped_target("http://[fe80::1%252]/").unwrap();
+        assert_eq!(target.url, "http://scoped-gateway.invalid/");
+        assert_eq!(target.authority, "[fe80::1%252]");
+        assert_eq!(target.addr.port(), 80);
+
+        assert!(scoped_target("http://[2001:db8::1]/").is_none());
+        assert!(scoped_target("http://10.0.0.1/").is_none());
+    }
+
+    #[test]
+    fn redirects_to_the_stand_in_name_point_back_at_the_gateway() {
+        let target = scoped_target("http://[fe80::1%252]:8080/").unwrap();
+        assert_eq!(
+            target.restore("http://scoped-gateway.invalid:8080/login"),
+            "http://[fe80::1%252]:8080/login"
+        );
+        assert_eq!(
+            target.restore("http://portal.test/login"),
+            "http://portal.test/login"
+        );
+    }
+}
@@ -1,14 +1,63 @@
+mod capport;
+mod config;
+mod dns;
+mod extract;
+mod fake;
+mod family;
+mod monitor;
+mod redirect;
+mod report;
+mod response;
+#[cfg(any(test, feature = "sim"))]
+mod sim;
+#[cfg(test)]
+mod testing;
+mod verdict;
+mod watch;
+
+use std::borrow::Cow;
+use std::cell::Cell;
 use std::error::Error;
 use std::fmt;
-use std::process::Command;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
 use std::result::Result;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use colored::Colorize;
 use regex::Regex;
-use reqwest::blocking::{Client, Response};
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+
+pub use capport::{
+    captive_api_status, captive_api_uri, detect_captive_api, query_captive_api, CaptiveApiStatus,
+    CaptivePortalStatus, DhcpLeaseSource,
+};
+pub use config::{
+    ConfigFile, ConfigLayers, EffectiveConfig, EndpointSetting, NetworkIdentity, Settings,
+};
+pub use dns::{Resolver, StubResolver, SystemResolver};
+pub use extract::{
+    decode_html_entities, extract_meta_refresh, extract_portal_url, extract_redirect_url,
+    portal_candidates, resolve_url, CandidateSource, PortalCandidate,
+};
+pub use fake::FakeNetworkClient;
+pub use family::{url_host, AddressFamily};
+#[cfg(target_os = "linux")]
+pub use monitor::NetlinkMonitor;
+pub use monitor::{monitor, route_monitor, GatewayTrigger, RouteCommandMonitor, RouteMonitor};
+use redirect::follow_redirects_until;
+pub use redirect::{follow_redirects, RedirectHop};
+pub use report::{
+    DetectionReport, FamilyResult, FoundBy, JsonReporter, ProbeOutcome, ProbeRecord, ProbeStage,
+    ReportEvent, ReportStatus, Reporter, SilentReporter, TerminalReporter,
+};
+pub use response::{ProbeError, ProbeResponse, MAX_BODY_BYTES};
+#[cfg(any(test, feature = "sim"))]
+pub use sim::{PortalSim, SimPortal};
+pub use verdict::{decide_verdict, Evidence, EvidenceKind, Verdict, VerdictStrategy};
+pub use watch::{watch, Clock, SystemClock, WatchOptions, WatchState, Watcher};
 
 #[derive(Debug)]
 pub enum ReauthfiError {
@@ -17,12 +66,15 @@ pub enum ReauthfiError {
     CommandFailed(String),
     UnsupportedPlatform,
     Setup(String),
+    Network(String),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DetectionResult {
     PortalFound(String),
     NoPortalDetected,
+    /// No portal right now, but the Captive Portal API reports when the session ends.
+    SessionActive(CaptivePortalStatus),
     NetworkIssues(Vec<String>),
 }
 
@@ -34,6 +86,7 @@ impl fmt::Display for ReauthfiError {
             ReauthfiError::CommandFailed(msg) => write!(f, "Command failed: {}", msg),
             ReauthfiError::UnsupportedPlatform => write!(f, "Unsupported platform"),
             ReauthfiError::Setup(msg) => write!(f, "Setup error: {}", msg),
+            ReauthfiError::Network(msg) => write!(f, "Network error: {}", msg),
         }
     }
 }
@@ -46,91 +99,497 @@ impl From<std::io::Error> for ReauthfiError {
     }
 }
 
+/// What the body of a successful probe must look like.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged, try_from = "RawBodyMatch")]
+pub enum BodyMatch {
+    /// The whole body, ignoring surrounding whitespace.
+    Exact(Cow<'static, str>),
+    Regex {
+        regex: Pattern,
+    },
+}
+
+impl BodyMatch {
+    /// A regex match; fails on an invalid pattern.
+    pub fn regex(pattern: &str) -> Result<Self, ReauthfiError> {
+        Ok(BodyMatch::Regex {
+            regex: Pattern::new(pattern)?,
+        })
+    }
+
+    pub fn matches(&self, body: &str) -> bool {
+        match self {
+            BodyMatch::Exact(expected) => body.trim() == expected.trim(),
+            BodyMatch::Regex { regex } => regex.0.is_match(body),
+        }
+    }
+}
+
+/// `BodyMatch` as written in a config file, before its regex is compiled.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawBodyMatch {
+    Exact(String),
+    Regex { regex: String },
+}
+
+impl TryFrom<RawBodyMatch> for BodyMatch {
+    type Error = String;
+
+    fn try_from(raw: RawBodyMatch) -> Result<Self, Self::Error> {
+        match raw {
+            RawBodyMatch::Exact(body) => Ok(BodyMatch::Exact(body.into())),
+            RawBodyMatch::Regex { regex } => Ok(BodyMatch::Regex {
+                regex: Pattern(compile(&regex)?),
+            }),
+        }
+    }
+}
+
+/// A regex compiled once, when the config is read, rather than for every probe.
 #[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Result<Self, ReauthfiError> {
+        compile(pattern).map(Pattern).map_err(ReauthfiError::Setup)
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|e| format!("invalid regex {:?}: {}", pattern, e))
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for Pattern {}
+
+impl Serialize for Pattern {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DetectionEndpoint {
-    pub name: &'static str,
-    pub url: &'static str,
+    pub name: Cow<'static, str>,
+    pub url: Cow<'static, str>,
     pub expected_status: Option<u16>,
+    pub expected_body: Option<BodyMatch>,
+}
+
+impl DetectionEndpoint {
+    pub fn new(
+        name: impl Into<Cow<'static, str>>,
+        url: impl Into<Cow<'static, str>>,
+        expected_status: Option<u16>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            url: url.into(),
+            expected_status,
+            expected_body: None,
+        }
+    }
+
+    pub fn expected_body(mut self, body: BodyMatch) -> Self {
+        self.expected_body = Some(body);
+        self
+    }
 }
 
-const MACOS_DETECTION_ENDPOINTS: &[DetectionEndpoint] = &[
+const APPLE_SUCCESS_PAGE: &str =
+    "<HTML><HEAD><TITLE>Success</TITLE></HEAD><BODY>Success</BODY></HTML>";
+
+const STANDARD_DETECTION_ENDPOINTS: &[DetectionEndpoint] = &[
     DetectionEndpoint {
-        name: "Apple",
-        url: "http://captive.apple.com/hotspot-detect.html",
+        name: Cow::Borrowed("Apple"),
+        url: Cow::Borrowed("http://captive.apple.com/hotspot-detect.html"),
         expected_status: None,
+        expected_body: Some(BodyMatch::Exact(Cow::Borrowed(APPLE_SUCCESS_PAGE))),
     },
     DetectionEndpoint {
-        name: "Google",
-        url: "http://connectivitycheck.gstatic.com/generate_204",
+        name: Cow::Borrowed("Google"),
+        url: Cow::Borrowed("http://connectivitycheck.gstatic.com/generate_204"),
         expected_status: Some(204),
+        expected_body: None,
     },
 ];
 
-#[derive(Debug)]
+/// Built-in presets are `static`s borrowing constant data; runtime configs are
+/// derived from them with [`DetectionConfig::to_builder`].
+#[derive(Debug, Clone)]
 pub struct DetectionConfig {
-    pub detection_endpoints: &'static [DetectionEndpoint],
+    pub detection_endpoints: Cow<'static, [DetectionEndpoint]>,
     pub gateway_command: &'static [&'static str],
     pub gateway_regex: &'static str,
-    pub gateway_endpoints: &'static [&'static str],
-    pub supports_wifi_reset: bool,
+    pub gateway_route_file: Option<&'static str>,
+    /// Looks up the IPv6 default router; the regex captures the address and,
+    /// optionally, the interface used as its zone.
+    pub gateway6_command: &'static [&'static str],
+    pub gateway6_regex: &'static str,
+    pub gateway_endpoints: Cow<'static, [Cow<'static, str>]>,
+    /// Host, or `host:port`, probed instead of the default gateway when set.
+    pub gateway_address: Option<Cow<'static, str>>,
+    pub wifi_backend: WifiBackend,
+    pub dhcp_leases: DhcpLeaseSource,
+}
+
+impl DetectionConfig {
+    /// Whether the configured Wi-Fi backend has its tools installed on this machine.
+    pub fn supports_wifi_reset(&self) -> bool {
+        self.wifi_backend.is_available()
+    }
+
+    pub fn to_builder(&self) -> DetectionConfigBuilder {
+        DetectionConfigBuilder {
+            config: self.clone(),
+        }
+    }
+}
+
+pub struct DetectionConfigBuilder {
+    config: DetectionConfig,
+}
+
+impl DetectionConfigBuilder {
+    /// Adds a detection endpoint, replacing any existing one with the same name.
+    pub fn endpoint(mut self, endpoint: DetectionEndpoint) -> Self {
+        let endpoints = self.config.detection_endpoints.to_mut();
+        match endpoints.iter_mut().find(|e| e.name == endpoint.name) {
+            Some(existing) => *existing = endpoint,
+            None => endpoints.push(endpoint),
+        }
+        self
+    }
+
+    /// Removes the detection endpoint named `name` (case-insensitive).
+    pub fn without_endpoint(mut self, name: &str) -> Self {
+        self.config
+            .detection_endpoints
+            .to_mut()
+            .retain(|e| !e.name.eq_ignore_ascii_case(name));
+        self
+    }
+
+    pub fn clear_endpoints(mut self) -> Self {
+        self.config.detection_endpoints = Cow::Owned(Vec::new());
+        self
+    }
+
+    /// Adds a path probed on the gateway, e.g. `/login.html`.
+    pub fn gateway_endpoint(mut self, path: impl Into<Cow<'static, str>>) -> Self {
+        let path = path.into();
+        let paths = self.config.gateway_endpoints.to_mut();
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+        self
+    }
+
+    pub fn clear_gateway_endpoints(mut self) -> Self {
+        self.config.gateway_endpoints = Cow::Owned(Vec::new());
+        self
+    }
+
+    /// Probes `address` (e.g. `10.0.0.1` or `127.0.0.1:8080`) instead of the default gateway.
+    pub fn gateway_address(mut self, address: impl Into<Cow<'static, str>>) -> Self {
+        self.config.gateway_address = Some(address.into());
+        self
+    }
+
+    pub fn wifi_backend(mut self, backend: WifiBackend) -> Self {
+        self.config.wifi_backend = backend;
+        self
+    }
+
+    pub fn dhcp_leases(mut self, source: DhcpLeaseSource) -> Self {
+        self.config.dhcp_leases = source;
+        self
+    }
+
+    pub fn build(self) -> DetectionConfig {
+        self.config
+    }
 }
 
 const MACOS_GATEWAY_COMMAND: &[&str] = &["route", "-n", "get", "default"];
 const MACOS_GATEWAY_REGEX: &str = r"gateway:\s+(\d+\.\d+\.\d+\.\d+)";
-const MACOS_GATEWAY_ENDPOINTS: &[&str] = &["/"];
+const MACOS_GATEWAY6_COMMAND: &[&str] = &["route", "-n", "get", "-inet6", "default"];
+const MACOS_GATEWAY6_REGEX: &str = r"gateway:\s+([0-9a-fA-F:]+)(?:%(\S+))?";
+const MACOS_GATEWAY_ENDPOINTS: &[Cow<'static, str>] = &[Cow::Borrowed("/")];
 
-static MACOS_CONFIG: DetectionConfig = DetectionConfig {
-    detection_endpoints: MACOS_DETECTION_ENDPOINTS,
+pub static MACOS_CONFIG: DetectionConfig = DetectionConfig {
+    detection_endpoints: Cow::Borrowed(STANDARD_DETECTION_ENDPOINTS),
     gateway_command: MACOS_GATEWAY_COMMAND,
     gateway_regex: MACOS_GATEWAY_REGEX,
-    gateway_endpoints: MACOS_GATEWAY_ENDPOINTS,
-    supports_wifi_reset: true,
+    gateway_route_file: None,
+    gateway6_command: MACOS_GATEWAY6_COMMAND,
+    gateway6_regex: MACOS_GATEWAY6_REGEX,
+    gateway_endpoints: Cow::Borrowed(MACOS_GATEWAY_ENDPOINTS),
+    gateway_address: None,
+    wifi_backend: WifiBackend::NetworkSetup,
+    dhcp_leases: DhcpLeaseSource::Ipconfig,
+};
+
+const LINUX_GATEWAY_COMMAND: &[&str] = &["ip", "route", "show", "default"];
+const LINUX_GATEWAY_REGEX: &str = r"default\s+via\s+(\d+\.\d+\.\d+\.\d+)";
+const LINUX_GATEWAY6_COMMAND: &[&str] = &["ip", "-6", "route", "show", "default"];
+const LINUX_GATEWAY6_REGEX: &str = r"default\s+via\s+([0-9a-fA-F:]+)(?:\s+dev\s+(\S+))?";
+const LINUX_ROUTE_FILE: &str = "/proc/net/route";
+const LINUX_GATEWAY_ENDPOINTS: &[Cow<'static, str>] = &[Cow::Borrowed("/")];
+const LINUX_LEASE_DIRS: &[&str] = &[
+    "/var/lib/NetworkManager",
+    "/run/systemd/netif/leases",
+    "/var/lib/dhcp",
+    "/var/lib/dhclient",
+];
+
+pub static LINUX_CONFIG: DetectionConfig = DetectionConfig {
+    detection_endpoints: Cow::Borrowed(STANDARD_DETECTION_ENDPOINTS),
+    gateway_command: LINUX_GATEWAY_COMMAND,
+    gateway_regex: LINUX_GATEWAY_REGEX,
+    gateway_route_file: Some(LINUX_ROUTE_FILE),
+    gateway6_command: LINUX_GATEWAY6_COMMAND,
+    gateway6_regex: LINUX_GATEWAY6_REGEX,
+    gateway_endpoints: Cow::Borrowed(LINUX_GATEWAY_ENDPOINTS),
+    gateway_address: None,
+    wifi_backend: WifiBackend::NetworkManager,
+    dhcp_leases: DhcpLeaseSource::LeaseFiles(LINUX_LEASE_DIRS),
 };
 
-fn detection_config() -> Result<&'static DetectionConfig, ReauthfiError> {
+/// The built-in preset for the current platform.
+pub fn detection_config() -> Result<&'static DetectionConfig, ReauthfiError> {
     #[cfg(target_os = "macos")]
     {
         Ok(&MACOS_CONFIG)
     }
 
-    #[cfg(not(target_os = "macos"))]
+    #[cfg(target_os = "linux")]
+    {
+        Ok(&LINUX_CONFIG)
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
     {
         Err(ReauthfiError::UnsupportedPlatform)
     }
 }
 
 pub trait NetworkClient: Send + Sync {
-    fn get(&self, url: &str, timeout: Duration) -> Result<Response, reqwest::Error>;
+    fn get(&self, url: &str, timeout: Duration) -> Result<ProbeResponse, ProbeError>;
+
+    /// Like [`NetworkClient::get`], but connects over `family` only. Clients
+    /// that cannot choose ignore the family.
+    fn get_over(
+        &self,
+        url: &str,
+        family: AddressFamily,
+        timeout: Duration,
+    ) -> Result<ProbeResponse, ProbeError> {
+        let _ = family;
+        self.get(url, timeout)
+    }
+
+    /// Like [`NetworkClient::get`], sending `headers` along with the request.
+    /// Clients that cannot send headers ignore them.
+    fn get_with_headers(
+        &self,
+        url: &str,
+        headers: &[(&str, &str)],
+        timeout: Duration,
+    ) -> Result<ProbeResponse, ProbeError> {
+        let _ = headers;
+        self.get(url, timeout)
+    }
+
+    /// How the client looks up names, for spotting portals that hijack DNS.
+    /// `None` skips that check.
+    fn resolver(&self) -> Option<Arc<dyn Resolver>> {
+        None
+    }
 }
 
 #[derive(Clone)]
 pub struct HttpClient {
     inner: Client,
+    request_timeout: Duration,
+    resolver: Arc<dyn Resolver>,
 }
 
 impl HttpClient {
     pub fn new(request_timeout_secs: u64) -> Result<Self, ReauthfiError> {
         let request_timeout = Duration::from_secs(request_timeout_secs);
+        let inner = Self::builder(request_timeout)
+            .build()
+            .map_err(|e| ReauthfiError::Setup(format!("failed to build http client: {}", e)))?;
+
+        Ok(Self {
+            inner,
+            request_timeout,
+            resolver: Arc::new(SystemResolver),
+        })
+    }
+
+    /// Checks DNS answers with `resolver` instead of the system's. Requests
+    /// still resolve through the system.
+    pub fn with_resolver(mut self, resolver: impl Resolver + 'static) -> Self {
+        self.resolver = Arc::new(resolver);
+        self
+    }
+
+    fn builder(request_timeout: Duration) -> reqwest::blocking::ClientBuilder {
         let connect_timeout = request_timeout.min(Duration::from_secs(2));
-        let inner = Client::builder()
+        Client::builder()
             .redirect(reqwest::redirect::Policy::none())
+            // Probes run side by side and get abandoned when another one wins, so each
+            // opens its own connection instead of inheriting a pooled one mid-use or one
+            // opened before the network turned captive.
+            .pool_max_idle_per_host(0)
             .timeout(request_timeout)
             .connect_timeout(connect_timeout)
-            .build()
-            .map_err(|e| ReauthfiError::Setup(format!("failed to build http client: {}", e)))?;
+    }
+
+    fn send(client: &Client, url: &str, timeout: Duration) -> Result<ProbeResponse, ProbeError> {
+        Self::send_with_headers(client, url, &[], timeout)
+    }
+
+    fn send_with_headers(
+        client: &Client,
+        url: &str,
+        headers: &[(&str, &str)],
+        timeout: Duration,
+    ) -> Result<ProbeResponse, ProbeError> {
+        let request = headers
+            .iter()
+            .fold(client.get(url), |request, (name, value)| {
+                request.header(*name, *value)
+            });
+        let response = request.timeout(timeout).send()?;
+        Ok(response.into())
+    }
+
+    /// Sends through a one-off client that connects to `addrs` for `host`.
+    fn send_to(
+        &self,
+        url: &str,
+        host: &str,
+        addrs: &[SocketAddr],
+        timeout: Duration,
+    ) -> Result<ProbeResponse, ProbeError> {
+        let client = Self::builder(self.request_timeout)
+            .resolve_to_addrs(host, addrs)
+            .build()?;
+        Self::send(&client, url, timeout)
+    }
 
-        Ok(Self { inner })
+    /// Link-local gateways are only reachable through their zone, which URLs
+    /// can carry but reqwest cannot parse, so a stand-in name is resolved to it.
+    /// The gateway still sees its own address as `Host`, and redirects to the
+    /// stand-in are pointed back at it.
+    fn send_scoped(
+        &self,
+        url: &str,
+        timeout: Duration,
+    ) -> Option<Result<ProbeResponse, ProbeError>> {
+        let target = family::scoped_target(url)?;
+        let send = || {
+            let client = Self::builder(self.request_timeout)
+                .resolve(family::STAND_IN, target.addr)
+                .build()?;
+            let response = client
+                .get(&target.url)
+                .header(reqwest::header::HOST, &target.authority)
+                .timeout(timeout)
+                .send()?;
+            Ok(ProbeResponse::from(response))
+        };
+        Some(send().map(|response| {
+            response
+                .map_header("location", |location| target.restore(location))
+                .with_url(url)
+        }))
     }
 }
 
 impl NetworkClient for HttpClient {
-    fn get(&self, url: &str, timeout: Duration) -> Result<Response, reqwest::Error> {
-        self.inner.get(url).timeout(timeout).send()
+    fn get(&self, url: &str, timeout: Duration) -> Result<ProbeResponse, ProbeError> {
+        self.send_scoped(url, timeout)
+            .unwrap_or_else(|| Self::send(&self.inner, url, timeout))
+    }
+
+    fn get_with_headers(
+        &self,
+        url: &str,
+        headers: &[(&str, &str)],
+        timeout: Duration,
+    ) -> Result<ProbeResponse, ProbeError> {
+        Self::send_with_headers(&self.inner, url, headers, timeout)
+    }
+
+    /// Resolves the host here, on the probe's own thread, and connects only to
+    /// the addresses of `family`.
+    fn get_over(
+        &self,
+        url: &str,
+        family: AddressFamily,
+        timeout: Duration,
+    ) -> Result<ProbeResponse, ProbeError> {
+        if let Some(result) = self.send_scoped(url, timeout) {
+            return result;
+        }
+        let parsed = reqwest::Url::parse(url).map_err(|e| ProbeError::Other(e.to_string()))?;
+        let port = parsed.port_or_known_default().unwrap_or(80);
+        let host = parsed
+            .host_str()
+            .ok_or_else(|| ProbeError::Other(format!("no host in {}", url)))?;
+        let literal: Option<IpAddr> = host.trim_matches(|c| c == '[' || c == ']').parse().ok();
+        let addrs: Vec<SocketAddr> = match literal {
+            Some(ip) => vec![SocketAddr::new(ip, port)],
+            None => (host, port)
+                .to_socket_addrs()
+                .map_err(|e| ProbeError::Other(format!("lookup of {} failed: {}", host, e)))?
+                .collect(),
+        };
+
+        let addrs: Vec<SocketAddr> = addrs
+            .into_iter()
+            .filter(|addr| family.includes(addr))
+            .collect();
+        match literal {
+            _ if addrs.is_empty() => Err(ProbeError::Connect),
+            Some(_) => Self::send(&self.inner, url, timeout),
+            None => self.send_to(url, host, &addrs, timeout),
+        }
+    }
+
+    fn resolver(&self) -> Option<Arc<dyn Resolver>> {
+        Some(self.resolver.clone())
     }
 }
 
 pub trait CommandRunner: Send + Sync {
     fn run(&self, cmd: &[&str]) -> Result<String, std::io::Error>;
+
+    /// Starts a long-lived process (e.g. a browser) without waiting for it to exit.
+    fn spawn(&self, cmd: &[&str]) -> Result<(), std::io::Error> {
+        self.run(cmd).map(|_| ())
+    }
+
+    /// Waits for a command with stdio detached, so a child it leaves behind
+    /// (e.g. a browser started by `xdg-open`) cannot hold our pipes open.
+    fn run_detached(&self, cmd: &[&str]) -> Result<(), std::io::Error> {
+        self.run(cmd).map(|_| ())
+    }
 }
 
 pub struct SystemCommandRunner;
@@ -142,11 +601,7 @@ impl CommandRunner for SystemCommandRunner {
             .output()?;
 
         if !output.status.success() {
-            let status_desc = output
-                .status
-                .code()
-                .map(|code| format!("exit code {}", code))
-                .unwrap_or_else(|| "terminated by signal".to_string());
+            let status_desc = describe_exit(output.status);
             let stderr = String::from_utf8_lossy(&output.stderr);
             let detail = stderr.trim();
             let msg = if detail.is_empty() {
@@ -155,66 +610,181 @@ impl CommandRunner for SystemCommandRunner {
                 format!("{} ({})", status_desc, detail)
             };
 
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, msg));
+            return Err(std::io::Error::other(msg));
         }
 
         Ok(String::from_utf8_lossy(&output.stdout).to_string())
     }
+
+    fn spawn(&self, cmd: &[&str]) -> Result<(), std::io::Error> {
+        std::process::Command::new(cmd[0])
+            .args(&cmd[1..])
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+            .map(|mut child| {
+                // Reaped in the background so watch and monitor sessions do not
+                // collect a zombie per opened portal.
+                thread::spawn(move || child.wait());
+            })
+    }
+
+    fn run_detached(&self, cmd: &[&str]) -> Result<(), std::io::Error> {
+        let status = std::process::Command::new(cmd[0])
+            .args(&cmd[1..])
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()?;
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(std::io::Error::other(describe_exit(status)))
+        }
+    }
+}
+
+fn describe_exit(status: std::process::ExitStatus) -> String {
+    status
+        .code()
+        .map(|code| format!("exit code {}", code))
+        .unwrap_or_else(|| "terminated by signal".to_string())
 }
 
 pub fn get_gateway_ip(
     config: &DetectionConfig,
     runner: &dyn CommandRunner,
 ) -> Result<String, ReauthfiError> {
-    let stdout = runner.run(config.gateway_command)?;
-    let re = Regex::new(config.gateway_regex).map_err(|_| ReauthfiError::NotFound)?;
+    if let Some(address) = &config.gateway_address {
+        return Ok(address.to_string());
+    }
+    let from_command = runner
+        .run(config.gateway_command)
+        .map_err(ReauthfiError::from)
+        .and_then(|stdout| parse_gateway_output(config.gateway_regex, &stdout));
+
+    match (from_command, config.gateway_route_file) {
+        (Ok(ip), _) => Ok(ip),
+        (Err(_), Some(path)) => {
+            let table = std::fs::read_to_string(path)?;
+            parse_proc_net_route(&table).ok_or(ReauthfiError::NotFound)
+        }
+        (Err(e), None) => Err(e),
+    }
+}
+
+/// The IPv6 default router, with its interface as the zone when it is
+/// link-local, e.g. `fe80::1%en0`.
+pub fn get_gateway_ipv6(
+    config: &DetectionConfig,
+    runner: &dyn CommandRunner,
+) -> Result<String, ReauthfiError> {
+    let stdout = runner.run(config.gateway6_command)?;
+    parse_gateway6_output(config.gateway6_regex, &stdout).ok_or(ReauthfiError::NotFound)
+}
 
-    re.captures(&stdout)
+fn parse_gateway6_output(gateway_regex: &str, stdout: &str) -> Option<String> {
+    let caps = Regex::new(gateway_regex).ok()?.captures(stdout)?;
+    let ip: std::net::Ipv6Addr = caps.get(1)?.as_str().parse().ok()?;
+    // Only link-local (fe80::/10) addresses need a zone to be reachable.
+    let link_local = ip.segments()[0] & 0xffc0 == 0xfe80;
+    match caps.get(2) {
+        Some(zone) if link_local => Some(format!("{}%{}", ip, zone.as_str())),
+        _ => Some(ip.to_string()),
+    }
+}
+
+fn parse_gateway_output(gateway_regex: &str, stdout: &str) -> Result<String, ReauthfiError> {
+    let re = Regex::new(gateway_regex).map_err(|_| ReauthfiError::NotFound)?;
+
+    re.captures(stdout)
         .and_then(|caps| caps.get(1))
         .map(|m| m.as_str().to_string())
         .ok_or(ReauthfiError::NotFound)
 }
 
-pub fn extract_meta_refresh(html: &str) -> Option<String> {
-    // Case-insensitive match for meta refresh with URL
-    let re = Regex::new(r#"(?i)content\s*=\s*["']?\d+\s*;\s*url\s*=\s*([^"'\s>]+)"#).ok()?;
-    re.captures(html)
-        .and_then(|caps| caps.get(1))
-        .map(|m| m.as_str())
-        .and_then(|url| {
-            if url.starts_with("http") {
-                Some(url.to_string())
-            } else {
-                None
+/// Finds the default gateway in the kernel routing table as exposed by `/proc/net/route`.
+/// Addresses are hex-encoded in host byte order.
+pub fn parse_proc_net_route(table: &str) -> Option<String> {
+    const RTF_GATEWAY: u32 = 0x2;
+
+    table.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 4 || fields[1] != "00000000" {
+            return None;
+        }
+
+        let flags = u32::from_str_radix(fields[3], 16).ok()?;
+        if flags & RTF_GATEWAY == 0 {
+            return None;
+        }
+
+        let gateway = u32::from_str_radix(fields[2], 16).ok()?;
+        Some(std::net::Ipv4Addr::from(gateway.to_ne_bytes()).to_string())
+    })
+}
+
+/// The login page named by a `Link: <https://portal/login>; rel="..."` header
+/// (RFC 8288): a `captive-portal` link, else a `login` one. Links with other
+/// relations, such as stylesheets, are ignored.
+pub fn link_header_url(response: &ProbeResponse) -> Option<String> {
+    const RELATIONS: [&str; 2] = ["captive-portal", "login"];
+
+    response
+        .header_values("link")
+        .flat_map(|v| v.split(','))
+        .filter_map(|link| {
+            let (_, rest) = link.split_once('<')?;
+            let (url, params) = rest.split_once('>')?;
+            let url = url.trim();
+            if !url.starts_with("http") {
+                return None;
             }
+            let rel = params
+                .split(';')
+                .filter_map(|param| param.trim().split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("rel"))?
+                .1
+                .trim()
+                .trim_matches('"')
+                .to_ascii_lowercase();
+            let rank = rel
+                .split_whitespace()
+                .filter_map(|rel| RELATIONS.iter().position(|known| *known == rel))
+                .min()?;
+            Some((rank, url.to_string()))
         })
+        .min_by_key(|(rank, _)| *rank)
+        .map(|(_, url)| url)
 }
 
-pub fn redirect_location_url(response: &Response) -> Option<String> {
-    if response.status().is_redirection() {
-        response
-            .headers()
-            .get("location")
-            .and_then(|v| v.to_str().ok())
-            .map(|s| s.to_string())
+/// The `Location` of a redirect, resolved against the URL that was requested.
+pub fn redirect_location_url(response: &ProbeResponse) -> Option<String> {
+    if response.is_redirection() {
+        let location = response.header_value("location")?;
+        resolve_url(response.url(), location).or_else(|| Some(location.to_string()))
     } else {
         None
     }
 }
+
 pub trait PortalOpener: Send + Sync {
-    fn open(&self, url: &str) -> Result<(), ReauthfiError>;
+    /// Opens `url` in a browser and returns the name of the launcher that handled it.
+    fn open(&self, url: &str) -> Result<String, ReauthfiError>;
 }
 
 pub struct MacPortalOpener;
 
 impl PortalOpener for MacPortalOpener {
-    fn open(&self, url: &str) -> Result<(), ReauthfiError> {
+    fn open(&self, url: &str) -> Result<String, ReauthfiError> {
         #[cfg(target_os = "macos")]
         {
-            let status = Command::new("open").arg(url).status()?;
+            let status = std::process::Command::new("open").arg(url).status()?;
 
             if status.success() {
-                Ok(())
+                Ok("open".to_string())
             } else {
                 let detail = status
                     .code()
@@ -232,202 +802,806 @@ impl PortalOpener for MacPortalOpener {
     }
 }
 
-pub struct WifiController;
-
-impl WifiController {
-    pub fn wifi_device() -> Result<String, ReauthfiError> {
-        let output = Command::new("networksetup")
-            .arg("-listallhardwareports")
-            .output()
-            .map_err(ReauthfiError::from)?;
-
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let re_block = Regex::new(r"(?s)Hardware Port:\s*(Wi-Fi|AirPort).*?Device:\s*([^\s]+)")
-            .map_err(|_| ReauthfiError::NotFound)?;
+const XDG_LAUNCHERS: &[&[&str]] = &[&["xdg-open"], &["gio", "open"]];
+const KNOWN_BROWSERS: &[&str] = &[
+    "firefox",
+    "google-chrome",
+    "chromium",
+    "chromium-browser",
+    "brave-browser",
+    "microsoft-edge",
+];
 
-        re_block
-            .captures(&stdout)
-            .and_then(|caps| caps.get(2).map(|m| m.as_str().to_string()))
-            .ok_or(ReauthfiError::NotFound)
+/// Expands a launcher template: "%s" marks where the URL goes, otherwise it is appended.
+fn launcher_command(template: &str, url: &str) -> Option<Vec<String>> {
+    let mut parts: Vec<String> = template
+        .split_whitespace()
+        .map(|part| part.replace("%s", url))
+        .collect();
+    if parts.is_empty() {
+        return None;
     }
-
-    pub fn reset_wifi(device: &str) -> Result<(), ReauthfiError> {
-        Command::new("networksetup")
-            .args(["-setairportpower", device, "off"])
-            .status()
-            .map_err(ReauthfiError::from)
-            .and_then(|status| {
-                if status.success() {
-                    Ok(())
-                } else {
-                    Err(ReauthfiError::CommandFailed(format!(
-                        "setairportpower off failed ({})",
-                        status
-                    )))
-                }
-            })?;
-
-        std::thread::sleep(std::time::Duration::from_secs(2));
-
-        Command::new("networksetup")
-            .args(["-setairportpower", device, "on"])
-            .status()
-            .map_err(ReauthfiError::from)
-            .and_then(|status| {
-                if status.success() {
-                    Ok(())
-                } else {
-                    Err(ReauthfiError::CommandFailed(format!(
-                        "setairportpower on failed ({})",
-                        status
-                    )))
-                }
-            })?;
-
-        Ok(())
+    if !template.contains("%s") {
+        parts.push(url.to_string());
     }
+    Some(parts)
 }
 
-pub fn print_network_not_ready(detail: Option<&dyn fmt::Display>) {
-    println!(
-        "{} Network not ready - this may be a first-time Wi-Fi connection",
-        "❌".red().bold()
-    );
-    println!("  Close any macOS network popup windows and try again");
-    println!("  Or wait a few seconds for the network to stabilize");
+/// Opens portals with a user-configured command such as `firefox --new-window %s`.
+pub struct CommandPortalOpener<'a> {
+    template: String,
+    commands: &'a dyn CommandRunner,
+}
 
-    if let Some(detail) = detail {
-        println!("  Detail: {}", detail);
+impl<'a> CommandPortalOpener<'a> {
+    pub fn new(template: impl Into<String>, commands: &'a dyn CommandRunner) -> Self {
+        Self {
+            template: template.into(),
+            commands,
+        }
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct Options {
-    pub timeout: u64,
+impl PortalOpener for CommandPortalOpener<'_> {
+    fn open(&self, url: &str) -> Result<String, ReauthfiError> {
+        let parts = launcher_command(&self.template, url)
+            .ok_or_else(|| ReauthfiError::Setup("opener command is empty".to_string()))?;
+        let cmd: Vec<&str> = parts.iter().map(String::as_str).collect();
+        self.commands
+            .spawn(&cmd)
+            .map_err(|e| ReauthfiError::CommandFailed(format!("{}: {}", cmd[0], e)))?;
+        Ok(cmd[0].to_string())
+    }
 }
 
-impl Default for Options {
-    fn default() -> Self {
-        Self { timeout: 5 }
+/// The configured opener command if any, otherwise the platform default.
+fn portal_opener<'a>(
+    options: &Options,
+    commands: &'a dyn CommandRunner,
+) -> Box<dyn PortalOpener + 'a> {
+    match &options.opener {
+        Some(template) => Box::new(CommandPortalOpener::new(template.clone(), commands)),
+        None => platform_opener(commands),
     }
 }
 
-pub struct DetectionContext<'a> {
-    pub config: &'a DetectionConfig,
-    pub net: Arc<dyn NetworkClient>,
-    pub commands: &'a dyn CommandRunner,
-    pub options: &'a Options,
-}
+fn platform_opener<'a>(commands: &'a dyn CommandRunner) -> Box<dyn PortalOpener + 'a> {
+    #[cfg(target_os = "macos")]
+    {
+        let _ = commands;
+        Box::new(MacPortalOpener)
+    }
 
-#[derive(Debug, Clone)]
-struct DetectionTarget {
-    name: String,
-    url: String,
-    expected_status: Option<u16>,
-    allow_meta_refresh: bool,
+    #[cfg(not(target_os = "macos"))]
+    {
+        Box::new(XdgPortalOpener::new(commands))
+    }
 }
 
-#[derive(Debug, Clone)]
-enum Outcome {
-    Portal(String),
-    ExpectedOk,
-    Mismatch(u16),
-    Issue(String),
+/// Opens portals on Linux desktops, trying `xdg-open`, `gio open`, `$BROWSER`
+/// and a few well-known browsers in that order.
+pub struct XdgPortalOpener<'a> {
+    commands: &'a dyn CommandRunner,
+    browser_env: Option<String>,
 }
 
-fn classify_parts(
-    target: &DetectionTarget,
-    status_code: u16,
-    location: Option<String>,
-    body: Option<String>,
-) -> Outcome {
-    if let Some(portal_url) = location {
-        return Outcome::Portal(portal_url);
+impl<'a> XdgPortalOpener<'a> {
+    pub fn new(commands: &'a dyn CommandRunner) -> Self {
+        Self {
+            commands,
+            browser_env: std::env::var("BROWSER").ok(),
+        }
     }
 
-    if let Some(expected) = target.expected_status {
-        if status_code == expected {
-            return Outcome::ExpectedOk;
-        }
+    fn browser_env_commands(&self, url: &str) -> Vec<Vec<String>> {
+        // $BROWSER is a colon-separated list of launcher templates.
+        self.browser_env
+            .as_deref()
+            .unwrap_or_default()
+            .split(':')
+            .filter_map(|entry| launcher_command(entry, url))
+            .collect()
     }
+}
 
-    if let Some(body) = body {
-        if target.allow_meta_refresh {
-            if let Some(url) = extract_meta_refresh(&body) {
-                return Outcome::Portal(url);
+impl PortalOpener for XdgPortalOpener<'_> {
+    fn open(&self, url: &str) -> Result<String, ReauthfiError> {
+        let mut failures: Vec<String> = Vec::new();
+
+        for launcher in XDG_LAUNCHERS {
+            let mut cmd = launcher.to_vec();
+            cmd.push(url);
+            match self.commands.run_detached(&cmd) {
+                Ok(_) => return Ok(launcher.join(" ")),
+                Err(e) => failures.push(format!("{}: {}", launcher.join(" "), e)),
             }
         }
 
-        if target.expected_status.is_none() && body.to_ascii_lowercase().contains("success") {
-            return Outcome::ExpectedOk;
+        for parts in self.browser_env_commands(url) {
+            let cmd: Vec<&str> = parts.iter().map(String::as_str).collect();
+            match self.commands.spawn(&cmd) {
+                Ok(_) => return Ok(format!("$BROWSER ({})", cmd[0])),
+                Err(e) => failures.push(format!("$BROWSER {}: {}", cmd[0], e)),
+            }
         }
-    }
 
-    Outcome::Mismatch(status_code)
+        for browser in KNOWN_BROWSERS {
+            match self.commands.spawn(&[browser, url]) {
+                Ok(_) => return Ok(browser.to_string()),
+                Err(e) => failures.push(format!("{}: {}", browser, e)),
+            }
+        }
+
+        Err(ReauthfiError::CommandFailed(format!(
+            "no browser launcher succeeded ({})",
+            failures.join(", ")
+        )))
+    }
 }
 
-fn classify_response(target: &DetectionTarget, response: Response) -> Outcome {
-    let location = redirect_location_url(&response);
-    let status = response.status();
-    let status_code = status.as_u16();
-    let should_parse_body =
-        status.is_success() && (target.allow_meta_refresh || target.expected_status.is_none());
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WifiBackend {
+    None,
+    /// macOS `networksetup -setairportpower`.
+    NetworkSetup,
+    /// Linux `nmcli` (radio, then device reconnect), falling back to `rfkill`.
+    NetworkManager,
+}
 
-    if should_parse_body {
-        match response.text() {
-            Ok(body) => classify_parts(target, status_code, location, Some(body)),
-            Err(_) => Outcome::Issue(format!("{}: failed to read body", target.name)),
+impl WifiBackend {
+    pub fn is_available(&self) -> bool {
+        match self {
+            WifiBackend::None => false,
+            WifiBackend::NetworkSetup => command_exists("networksetup"),
+            // A reset needs the Wi-Fi device first, which nmcli or iw finds.
+            WifiBackend::NetworkManager => {
+                command_exists("nmcli") || (command_exists("iw") && command_exists("rfkill"))
+            }
         }
-    } else {
-        classify_parts(target, status_code, location, None)
     }
 }
 
-fn error_reason(name: &str, err: &reqwest::Error, timeout: Duration) -> String {
-    if err.is_timeout() {
-        format!("{}: timeout ({}s)", name, timeout.as_secs())
-    } else if err.is_connect() {
-        format!("{}: connect error", name)
-    } else {
-        format!("{}: error {}", name, err)
-    }
+fn command_exists(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
 }
 
-fn run_detection(targets: &[DetectionTarget], ctx: &DetectionContext) -> DetectionResult {
-    let mut errors: Vec<String> = Vec::new();
-    let mut saw_expected_ok = false;
+pub trait WifiControl: Send + Sync {
+    fn is_available(&self) -> bool;
+    fn wifi_device(&self) -> Result<String, ReauthfiError>;
+    /// Name of the network `device` is associated with.
+    fn ssid(&self, device: &str) -> Result<String, ReauthfiError>;
+    fn reset_wifi(&self, device: &str) -> Result<(), ReauthfiError>;
+    /// Blocks while the interface settles; mocks can return immediately.
+    fn wait(&self, duration: Duration);
+}
 
-    for target in targets {
-        let request_timeout = Duration::from_secs(ctx.options.timeout);
+pub struct WifiController<'a> {
+    backend: WifiBackend,
+    commands: &'a dyn CommandRunner,
+    available: bool,
+    sleep: &'a (dyn Fn(Duration) + Send + Sync),
+}
 
-        let outcome = match ctx.net.get(&target.url, request_timeout) {
-            Ok(response) => classify_response(target, response),
-            Err(e) => Outcome::Issue(error_reason(&target.name, &e, request_timeout)),
-        };
+impl<'a> WifiController<'a> {
+    pub fn new(config: &DetectionConfig, commands: &'a dyn CommandRunner) -> Self {
+        Self {
+            backend: config.wifi_backend,
+            commands,
+            available: config.supports_wifi_reset(),
+            sleep: &thread::sleep,
+        }
+    }
 
-        match outcome {
-            Outcome::Portal(url) => {
-                println!("    {} {} redirect detected", "✓".green(), target.name);
-                return DetectionResult::PortalFound(url);
-            }
-            Outcome::Issue(msg) => {
-                if target.allow_meta_refresh {
-                    println!(
-                        "    {} {} unreachable (ignored)",
-                        "⚠️".yellow(),
-                        target.name
-                    );
-                } else {
-                    println!("    {} {} failed", "✗".red(), target.name);
-                }
-                errors.push(msg);
-            }
-            Outcome::Mismatch(status) => {
-                errors.push(format!("{}: status {}", target.name, status));
+    fn command(&self, cmd: &[&str]) -> Result<String, ReauthfiError> {
+        self.commands
+            .run(cmd)
+            .map_err(|e| ReauthfiError::CommandFailed(format!("{} failed ({})", cmd.join(" "), e)))
+    }
+
+    fn cycle(&self, off: &[&str], on: &[&str]) -> Result<(), ReauthfiError> {
+        self.command(off)?;
+        self.wait(Duration::from_secs(2));
+        self.command(on).map(|_| ())
+    }
+}
+
+impl WifiControl for WifiController<'_> {
+    fn is_available(&self) -> bool {
+        self.available
+    }
+
+    fn wifi_device(&self) -> Result<String, ReauthfiError> {
+        match self.backend {
+            WifiBackend::None => Err(ReauthfiError::UnsupportedPlatform),
+            WifiBackend::NetworkSetup => {
+                let stdout = self.command(&["networksetup", "-listallhardwareports"])?;
+                parse_networksetup_device(&stdout).ok_or(ReauthfiError::NotFound)
+            }
+            WifiBackend::NetworkManager => self
+                .command(&["nmcli", "-t", "-f", "DEVICE,TYPE", "device"])
+                .ok()
+                .and_then(|stdout| parse_nmcli_device(&stdout))
+                .or_else(|| {
+                    self.command(&["iw", "dev"])
+                        .ok()
+                        .and_then(|stdout| parse_iw_device(&stdout))
+                })
+                .ok_or(ReauthfiError::NotFound),
+        }
+    }
+
+    fn ssid(&self, device: &str) -> Result<String, ReauthfiError> {
+        match self.backend {
+            WifiBackend::None => Err(ReauthfiError::UnsupportedPlatform),
+            WifiBackend::NetworkSetup => {
+                let stdout = self.command(&["networksetup", "-getairportnetwork", device])?;
+                parse_networksetup_ssid(&stdout).ok_or(ReauthfiError::NotFound)
+            }
+            WifiBackend::NetworkManager => self
+                .command(&["nmcli", "-t", "-f", "ACTIVE,SSID", "device", "wifi"])
+                .ok()
+                .and_then(|stdout| parse_nmcli_ssid(&stdout))
+                .or_else(|| {
+                    self.command(&["iwgetid", "-r", device])
+                        .ok()
+                        .map(|stdout| stdout.trim().to_string())
+                        .filter(|ssid| !ssid.is_empty())
+                })
+                .ok_or(ReauthfiError::NotFound),
+        }
+    }
+
+    fn reset_wifi(&self, device: &str) -> Result<(), ReauthfiError> {
+        match self.backend {
+            WifiBackend::None => Err(ReauthfiError::UnsupportedPlatform),
+            WifiBackend::NetworkSetup => self.cycle(
+                &["networksetup", "-setairportpower", device, "off"],
+                &["networksetup", "-setairportpower", device, "on"],
+            ),
+            WifiBackend::NetworkManager => {
+                let strategies: [(&[&str], &[&str]); 3] = [
+                    (
+                        &["nmcli", "radio", "wifi", "off"],
+                        &["nmcli", "radio", "wifi", "on"],
+                    ),
+                    (
+                        &["nmcli", "device", "disconnect", device],
+                        &["nmcli", "device", "connect", device],
+                    ),
+                    (&["rfkill", "block", "wifi"], &["rfkill", "unblock", "wifi"]),
+                ];
+
+                let mut last_err = ReauthfiError::NotFound;
+                for (i, (off, on)) in strategies.iter().enumerate() {
+                    if let Err(e) = self.command(off) {
+                        last_err = e;
+                        continue;
+                    }
+                    self.wait(Duration::from_secs(2));
+                    return self.command(on).map(|_| ()).or_else(|e| {
+                        // Never leave the radio off: retry, then try the later
+                        // strategies' "on" commands.
+                        std::iter::once(on)
+                            .chain(strategies[i + 1..].iter().map(|(_, on)| on))
+                            .find_map(|on| self.command(on).ok())
+                            .map(|_| ())
+                            .ok_or(e)
+                    });
+                }
+                Err(last_err)
             }
-            Outcome::ExpectedOk => {
+        }
+    }
+
+    fn wait(&self, duration: Duration) {
+        (self.sleep)(duration)
+    }
+}
+
+fn parse_networksetup_device(stdout: &str) -> Option<String> {
+    let re_block = Regex::new(r"(?s)Hardware Port:\s*(Wi-Fi|AirPort).*?Device:\s*([^\s]+)").ok()?;
+
+    re_block
+        .captures(stdout)
+        .and_then(|caps| caps.get(2).map(|m| m.as_str().to_string()))
+}
+
+fn parse_nmcli_device(stdout: &str) -> Option<String> {
+    stdout.lines().find_map(|line| {
+        let (device, kind) = line.rsplit_once(':')?;
+        (kind.trim() == "wifi").then(|| device.to_string())
+    })
+}
+
+fn parse_iw_device(stdout: &str) -> Option<String> {
+    stdout.lines().find_map(|line| {
+        line.trim()
+            .strip_prefix("Interface ")
+            .map(|name| name.trim().to_string())
+    })
+}
+
+fn parse_networksetup_ssid(stdout: &str) -> Option<String> {
+    stdout
+        .trim()
+        .strip_prefix("Current Wi-Fi Network:")
+        .map(|ssid| ssid.trim().to_string())
+}
+
+fn parse_nmcli_ssid(stdout: &str) -> Option<String> {
+    // Terse nmcli output escapes ':' inside values as '\:'.
+    stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("yes:"))
+        .map(|ssid| ssid.replace("\\:", ":"))
+        .filter(|ssid| !ssid.is_empty())
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub timeout: u64,
+    /// Open the detected portal in a browser (`--no-open` turns this off).
+    pub open: bool,
+    /// Write only the portal URL to stdout (`--print-url`).
+    pub print_url: bool,
+    /// Allow toggling Wi-Fi when the network is not ready (`--no-wifi-reset` turns this off).
+    pub wifi_reset: bool,
+    /// Print a single JSON document instead of progress output (`--json`).
+    pub json: bool,
+    /// Command used instead of the platform browser launcher; see [`CommandPortalOpener`].
+    pub opener: Option<String>,
+    /// Requests spent following a portal's redirects to its landing page
+    /// (`--follow-redirects`); 0 keeps the first `Location`.
+    pub follow_redirects: u32,
+    /// Print every step, including redirect chains (`--verbose`).
+    pub verbose: bool,
+    /// How the probes' answers are weighed into a verdict (`--verdict`).
+    pub verdict: VerdictStrategy,
+    /// Resolve the probe hosts to spot portals that hijack DNS (`--no-dns-check`
+    /// turns this off).
+    pub dns_check: bool,
+    /// Also resolve a made-up name, where any answer is a hijack
+    /// (`--no-nxdomain-check` turns this off, for resolvers that answer unknown
+    /// names with ads).
+    pub nxdomain_check: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            timeout: 5,
+            open: true,
+            print_url: false,
+            wifi_reset: true,
+            json: false,
+            opener: None,
+            follow_redirects: 0,
+            verbose: false,
+            verdict: VerdictStrategy::AnyPortal,
+            dns_check: true,
+            nxdomain_check: true,
+        }
+    }
+}
+
+pub struct DetectionContext<'a> {
+    pub config: &'a DetectionConfig,
+    pub net: Arc<dyn NetworkClient>,
+    pub commands: &'a dyn CommandRunner,
+    pub options: &'a Options,
+    pub reporter: &'a dyn Reporter,
+}
+
+#[derive(Debug, Clone)]
+struct DetectionTarget {
+    stage: ProbeStage,
+    /// Set on dual-stack networks and for gateways; `None` lets the OS choose.
+    family: Option<AddressFamily>,
+    name: String,
+    url: String,
+    expected_status: Option<u16>,
+    expected_body: Option<BodyMatch>,
+    allow_page_redirect: bool,
+    /// For DNS checks of a made-up name, which must not resolve.
+    nonexistent: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Outcome {
+    Portal(String),
+    /// HTTP 511 (RFC 6585); carries the login URL.
+    AuthenticationRequired(String),
+    ExpectedOk,
+    Mismatch(u16),
+    /// The expected status, but not the expected page, and no link to a portal in it.
+    BodyMismatch,
+    Issue(String),
+}
+
+fn classify_parts(
+    target: &DetectionTarget,
+    status_code: u16,
+    location: Option<String>,
+    body: Option<String>,
+) -> Outcome {
+    if let Some(portal_url) = location {
+        return Outcome::Portal(portal_url);
+    }
+
+    if target.expected_status == Some(status_code) && target.expected_body.is_none() {
+        return Outcome::ExpectedOk;
+    }
+
+    if let Some(body) = body {
+        if target.allow_page_redirect {
+            if let Some(url) = extract_redirect_url(&body, &target.url) {
+                return Outcome::Portal(url);
+            }
+        }
+
+        if let Some(expected) = &target.expected_body {
+            let status_ok = match target.expected_status {
+                Some(status) => status == status_code,
+                None => (200..300).contains(&status_code),
+            };
+            if status_ok {
+                if expected.matches(&body) {
+                    return Outcome::ExpectedOk;
+                }
+                return extract_portal_url(&body, &target.url)
+                    .map_or(Outcome::BodyMismatch, Outcome::Portal);
+            }
+        }
+
+        // Content where an exact status was expected (200 on generate_204) is a page
+        // the portal injected; without a link in it, the probe URL shows that page.
+        if target.expected_status.is_some()
+            && (200..300).contains(&status_code)
+            && !body.trim().is_empty()
+        {
+            return Outcome::Portal(
+                extract_portal_url(&body, &target.url).unwrap_or_else(|| target.url.clone()),
+            );
+        }
+    }
+
+    Outcome::Mismatch(status_code)
+}
+
+/// A 511 names its login page in a `Link` header or the body; without either,
+/// the probe URL itself shows the portal's login instructions.
+fn classify_authentication_required(
+    target: &DetectionTarget,
+    link: Option<String>,
+    body: Option<&str>,
+) -> Outcome {
+    let url = link
+        .or_else(|| body.and_then(|body| extract_portal_url(body, &target.url)))
+        .unwrap_or_else(|| target.url.clone());
+    Outcome::AuthenticationRequired(url)
+}
+
+fn classify_response(target: &DetectionTarget, response: ProbeResponse) -> Outcome {
+    if response.status() == 511 {
+        let link = link_header_url(&response);
+        let body = response.text().ok();
+        return classify_authentication_required(target, link, body.as_deref());
+    }
+
+    let location = redirect_location_url(&response);
+    let status_code = response.status();
+    let should_parse_body = response.is_success()
+        && (target.expected_body.is_some() || target.expected_status != Some(status_code));
+
+    if should_parse_body {
+        match response.text() {
+            Ok(body) => classify_parts(target, status_code, location, Some(body)),
+            Err(_) => Outcome::Issue(format!("{}: failed to read body", target.name)),
+        }
+    } else {
+        classify_parts(target, status_code, location, None)
+    }
+}
+
+fn error_reason(name: &str, err: &ProbeError, timeout: Duration) -> String {
+    match err {
+        ProbeError::Timeout => format!("{}: timeout ({}s)", name, timeout.as_secs()),
+        ProbeError::Connect => format!("{}: connect error", name),
+        ProbeError::Tls(_) => format!("{}: TLS error", name),
+        ProbeError::Other(msg) => format!("{}: error {}", name, msg),
+    }
+}
+
+fn report_probe(
+    ctx: &DetectionContext,
+    target: &DetectionTarget,
+    status_code: Option<u16>,
+    outcome: &Outcome,
+    elapsed: Duration,
+) {
+    let (outcome, detail) = match outcome {
+        Outcome::Portal(url) => (ProbeOutcome::Portal, Some(url.as_str())),
+        Outcome::AuthenticationRequired(url) => {
+            (ProbeOutcome::AuthenticationRequired, Some(url.as_str()))
+        }
+        Outcome::ExpectedOk => (ProbeOutcome::Ok, None),
+        Outcome::Mismatch(_) => (ProbeOutcome::Mismatch, None),
+        Outcome::BodyMismatch => (ProbeOutcome::BodyMismatch, None),
+        Outcome::Issue(msg) => (ProbeOutcome::Error, Some(msg.as_str())),
+    };
+
+    ctx.reporter.report(&ReportEvent::ProbeOutcome {
+        stage: target.stage,
+        family: target.family,
+        endpoint: &target.name,
+        url: &target.url,
+        outcome,
+        status_code,
+        elapsed,
+        detail,
+    });
+}
+
+struct ProbeResult {
+    status_code: Option<u16>,
+    outcome: Outcome,
+    /// From the probe to the landing page when redirects are followed.
+    hops: Vec<RedirectHop>,
+    evidence: Option<EvidenceKind>,
+    elapsed: Duration,
+}
+
+/// Sends one probe and classifies the answer. Runs on its own thread, so it only
+/// gets owned data and reports nothing itself.
+fn probe(
+    net: &dyn NetworkClient,
+    target: &DetectionTarget,
+    timeout: Duration,
+    redirect_limit: u32,
+    cancel: &AtomicBool,
+) -> Option<ProbeResult> {
+    match target.stage {
+        ProbeStage::Dns => dns::check(net, target, timeout, redirect_limit, cancel),
+        _ => fetch(net, target, timeout, redirect_limit, cancel),
+    }
+}
+
+/// Requests the target's URL, following a portal's redirects up to `redirect_limit`.
+/// `None` when `cancel` was set before the request went out.
+fn fetch(
+    net: &dyn NetworkClient,
+    target: &DetectionTarget,
+    timeout: Duration,
+    redirect_limit: u32,
+    cancel: &AtomicBool,
+) -> Option<ProbeResult> {
+    if cancel.load(Ordering::Relaxed) {
+        return None;
+    }
+    let started = Instant::now();
+    let mut tls_error = false;
+    let response = match target.family {
+        Some(family) => net.get_over(&target.url, family, timeout),
+        None => net.get(&target.url, timeout),
+    };
+    let (status_code, outcome) = match response {
+        Ok(response) => (Some(response.status()), classify_response(target, response)),
+        Err(e) => {
+            tls_error = matches!(e, ProbeError::Tls(_));
+            (
+                None,
+                Outcome::Issue(error_reason(&target.name, &e, timeout)),
+            )
+        }
+    };
+    let evidence = evidence_kind(target, status_code, &outcome, tls_error);
+
+    let mut hops = Vec::new();
+    if redirect_limit > 0 {
+        if let Outcome::Portal(url) | Outcome::AuthenticationRequired(url) = &outcome {
+            hops.push(RedirectHop {
+                url: target.url.clone(),
+                status_code,
+            });
+            if *url != target.url {
+                hops.extend(follow_redirects_until(
+                    net,
+                    url,
+                    redirect_limit,
+                    timeout,
+                    cancel,
+                ));
+            }
+        }
+    }
+    let outcome = match (outcome, hops.last()) {
+        (Outcome::Portal(_), Some(landing)) => Outcome::Portal(landing.url.clone()),
+        (Outcome::AuthenticationRequired(_), Some(landing)) => {
+            Outcome::AuthenticationRequired(landing.url.clone())
+        }
+        (outcome, _) => outcome,
+    };
+
+    Some(ProbeResult {
+        status_code,
+        outcome,
+        hops,
+        evidence,
+        elapsed: started.elapsed(),
+    })
+}
+
+/// What a probe's answer says about the network. Gateways serve all kinds of pages,
+/// so only a way to a portal counts from them.
+fn evidence_kind(
+    target: &DetectionTarget,
+    status_code: Option<u16>,
+    outcome: &Outcome,
+    tls_error: bool,
+) -> Option<EvidenceKind> {
+    let standard = target.stage == ProbeStage::Standard;
+    match outcome {
+        Outcome::Portal(_) if status_code.is_some_and(|code| (300..400).contains(&code)) => {
+            Some(EvidenceKind::Redirect)
+        }
+        Outcome::Portal(_) => Some(EvidenceKind::InjectedPage),
+        Outcome::AuthenticationRequired(_) => Some(EvidenceKind::AuthenticationRequired),
+        Outcome::BodyMismatch => Some(EvidenceKind::BodyMismatch),
+        Outcome::Mismatch(_) if standard => Some(EvidenceKind::StatusMismatch),
+        Outcome::ExpectedOk if standard => Some(EvidenceKind::Passed),
+        Outcome::Issue(_) if tls_error => Some(EvidenceKind::TlsAnomaly),
+        _ => None,
+    }
+}
+
+/// Sends every probe of every stage at once and reports the outcomes as they
+/// arrive, then weighs them into a verdict. With [`VerdictStrategy::AnyPortal`]
+/// the first portal is returned right away; one found through DNS waits until
+/// no standard probe can still pass. Without a portal each stage is summed up
+/// on its own, in order.
+///
+/// Once a portal is returned the other probes are cancelled: those not yet sent
+/// are skipped and redirect chains stop at the next hop. Probes that had already
+/// finished are still reported; a request already under way cannot be
+/// interrupted, so it finishes in the background and is dropped.
+fn run_stages(stages: &[Vec<DetectionTarget>], ctx: &DetectionContext) -> Vec<DetectionResult> {
+    let timeout = Duration::from_secs(ctx.options.timeout);
+    let strategy = ctx.options.verdict;
+    let (sender, results) = mpsc::channel();
+    let cancel = Arc::new(AtomicBool::new(false));
+
+    for (stage, targets) in stages.iter().enumerate() {
+        for (index, target) in targets.iter().enumerate() {
+            ctx.reporter.report(&ReportEvent::ProbeStarted {
+                stage: target.stage,
+                family: target.family,
+                endpoint: &target.name,
+                url: &target.url,
+            });
+            let sender = sender.clone();
+            let net = ctx.net.clone();
+            let target = target.clone();
+            let redirect_limit = ctx.options.follow_redirects;
+            let cancel = cancel.clone();
+            thread::spawn(move || {
+                if let Some(result) = probe(net.as_ref(), &target, timeout, redirect_limit, &cancel)
+                {
+                    let _ = sender.send((stage, index, result));
+                }
+            });
+        }
+    }
+    drop(sender);
+
+    let mut outcomes: Vec<Vec<Option<Outcome>>> = stages
+        .iter()
+        .map(|targets| vec![None; targets.len()])
+        .collect();
+    let mut evidence = Vec::new();
+    let mut record = |target: &DetectionTarget, result: ProbeResult| {
+        report_probe(
+            ctx,
+            target,
+            result.status_code,
+            &result.outcome,
+            result.elapsed,
+        );
+        if !result.hops.is_empty() {
+            ctx.reporter.report(&ReportEvent::RedirectChain {
+                stage: target.stage,
+                endpoint: &target.name,
+                hops: &result.hops,
+            });
+        }
+        if let Some(kind) = result.evidence {
+            evidence.push(Evidence {
+                kind,
+                stage: target.stage,
+                endpoint: target.name.clone(),
+                url: target.url.clone(),
+                portal_url: match &result.outcome {
+                    Outcome::Portal(url) | Outcome::AuthenticationRequired(url) => {
+                        Some(url.clone())
+                    }
+                    _ => None,
+                },
+            });
+        }
+        result.outcome
+    };
+    // A portal found through DNS only counts once no standard probe can pass.
+    let standard_may_pass = |outcomes: &[Vec<Option<Outcome>>]| {
+        stages
+            .iter()
+            .zip(outcomes)
+            .flat_map(|(targets, outcomes)| targets.iter().zip(outcomes))
+            .any(|(target, outcome)| {
+                target.stage == ProbeStage::Standard
+                    && matches!(outcome, None | Some(Outcome::ExpectedOk))
+            })
+    };
+    let mut dns_portal = false;
+    for (stage, index, result) in results.iter() {
+        let target = &stages[stage][index];
+        let outcome = record(target, result);
+        let found_portal = matches!(
+            outcome,
+            Outcome::Portal(_) | Outcome::AuthenticationRequired(_)
+        );
+        outcomes[stage][index] = Some(outcome);
+        if strategy != VerdictStrategy::AnyPortal {
+            continue;
+        }
+        dns_portal |= found_portal && target.stage == ProbeStage::Dns;
+        if (found_portal && target.stage != ProbeStage::Dns)
+            || (dns_portal && !standard_may_pass(&outcomes))
+        {
+            cancel.store(true, Ordering::Relaxed);
+            break;
+        }
+    }
+    // Probes that finished alongside the first portal still belong in the report.
+    for (stage, index, result) in results.try_iter() {
+        outcomes[stage][index] = Some(record(&stages[stage][index], result));
+    }
+
+    if let Some(verdict) = decide_verdict(strategy, &evidence) {
+        ctx.reporter
+            .report(&ReportEvent::Verdict { verdict: &verdict });
+        if let (true, Some(url)) = (verdict.captive, verdict.portal_url) {
+            return vec![DetectionResult::PortalFound(url)];
+        }
+    }
+
+    stages
+        .iter()
+        .zip(outcomes)
+        .map(|(targets, outcomes)| stage_result(targets, outcomes))
+        .collect()
+}
+
+/// Sums up a stage without a portal: any expected answer means there is none.
+fn stage_result(targets: &[DetectionTarget], outcomes: Vec<Option<Outcome>>) -> DetectionResult {
+    let mut errors: Vec<String> = Vec::new();
+    let mut saw_expected_ok = false;
+
+    for (target, outcome) in targets.iter().zip(outcomes) {
+        match outcome {
+            Some(Outcome::Issue(msg)) => {
+                errors.push(msg);
+            }
+            Some(Outcome::Mismatch(status)) => {
+                errors.push(format!("{}: status {}", target.name, status));
+            }
+            Some(Outcome::BodyMismatch) => {
+                errors.push(format!("{}: unexpected page", target.name));
+            }
+            Some(Outcome::ExpectedOk) => {
                 saw_expected_ok = true;
             }
+            Some(Outcome::Portal(_) | Outcome::AuthenticationRequired(_)) | None => {}
         }
     }
 
@@ -440,52 +1614,145 @@ fn run_detection(targets: &[DetectionTarget], ctx: &DetectionContext) -> Detecti
     }
 }
 
-pub fn detect_standard(ctx: &DetectionContext) -> DetectionResult {
-    let endpoints = ctx.config.detection_endpoints;
+fn run_detection(targets: Vec<DetectionTarget>, ctx: &DetectionContext) -> DetectionResult {
+    run_stages(&[targets], ctx)
+        .pop()
+        .unwrap_or(DetectionResult::NoPortalDetected)
+}
+
+/// The default router of each address family that has one, or the configured
+/// gateway address alone.
+fn find_gateways(ctx: &DetectionContext) -> Vec<(AddressFamily, String)> {
+    let gateways: Vec<(AddressFamily, String)> = match &ctx.config.gateway_address {
+        Some(address) => vec![(AddressFamily::of_address(address), address.to_string())],
+        None => [
+            (
+                AddressFamily::Ipv4,
+                get_gateway_ip(ctx.config, ctx.commands),
+            ),
+            (
+                AddressFamily::Ipv6,
+                get_gateway_ipv6(ctx.config, ctx.commands),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(family, address)| Some((family, address.ok()?)))
+        .collect(),
+    };
+
+    for (family, address) in &gateways {
+        ctx.reporter.report(&ReportEvent::GatewayFound {
+            family: *family,
+            address,
+        });
+    }
+    gateways
+}
+
+/// Dual-stack networks get a probe per family, named after it, since a portal
+/// may intercept only one of them.
+fn probe_families(gateways: &[(AddressFamily, String)]) -> Vec<Option<AddressFamily>> {
+    let has = |family| gateways.iter().any(|(f, _)| *f == family);
+    if has(AddressFamily::Ipv4) && has(AddressFamily::Ipv6) {
+        vec![Some(AddressFamily::Ipv4), Some(AddressFamily::Ipv6)]
+    } else {
+        vec![None]
+    }
+}
+
+fn target_name(name: &str, family: Option<AddressFamily>) -> String {
+    match family {
+        Some(family) => format!("{} ({})", name, family),
+        None => name.to_string(),
+    }
+}
+
+/// The probes for the well-known endpoints; announces the stage when there are any.
+fn standard_targets(
+    ctx: &DetectionContext,
+    gateways: &[(AddressFamily, String)],
+) -> Vec<DetectionTarget> {
+    let endpoints = &ctx.config.detection_endpoints;
     if endpoints.is_empty() {
-        return DetectionResult::NoPortalDetected;
+        return Vec::new();
     }
 
-    println!(
-        "  {} Checking captive portal endpoints ({} total)...",
-        "•".yellow(),
-        endpoints.len()
-    );
+    let families = probe_families(gateways);
+    ctx.reporter.report(&ReportEvent::StageStarted {
+        stage: ProbeStage::Standard,
+        endpoints: endpoints.len() * families.len(),
+    });
 
-    let targets: Vec<DetectionTarget> = endpoints
+    families
         .iter()
-        .map(|endpoint| DetectionTarget {
-            name: endpoint.name.to_string(),
-            url: endpoint.url.to_string(),
-            expected_status: endpoint.expected_status,
-            allow_meta_refresh: false,
+        .flat_map(|family| {
+            endpoints.iter().map(|endpoint| DetectionTarget {
+                stage: ProbeStage::Standard,
+                family: *family,
+                name: target_name(&endpoint.name, *family),
+                url: endpoint.url.to_string(),
+                expected_status: endpoint.expected_status,
+                expected_body: endpoint.expected_body.clone(),
+                allow_page_redirect: false,
+                nonexistent: false,
+            })
         })
-        .collect();
-
-    run_detection(&targets, ctx)
+        .collect()
 }
 
-pub fn detect_gateway(ctx: &DetectionContext) -> DetectionResult {
-    let gateway_ip = match get_gateway_ip(ctx.config, ctx.commands) {
-        Ok(ip) => ip,
-        Err(_) => return DetectionResult::NetworkIssues(vec!["gateway_ip".to_string()]),
-    };
+/// The probes for each gateway's paths, or the failure to find any gateway.
+fn gateway_targets(
+    ctx: &DetectionContext,
+    gateways: &[(AddressFamily, String)],
+) -> Result<Vec<DetectionTarget>, DetectionResult> {
+    if gateways.is_empty() {
+        return Err(DetectionResult::NetworkIssues(vec![
+            "gateway_ip".to_string()
+        ]));
+    }
 
-    println!("  {} Checking gateway endpoints...", "•".yellow());
+    let named = probe_families(gateways).len() > 1;
+    let paths = &ctx.config.gateway_endpoints;
+    ctx.reporter.report(&ReportEvent::StageStarted {
+        stage: ProbeStage::Gateway,
+        endpoints: paths.len() * gateways.len(),
+    });
 
-    let targets: Vec<DetectionTarget> = ctx
-        .config
-        .gateway_endpoints
+    Ok(gateways
         .iter()
-        .map(|endpoint| DetectionTarget {
-            name: format!("Gateway{}", endpoint),
-            url: format!("http://{}{}", gateway_ip, endpoint),
-            expected_status: None,
-            allow_meta_refresh: true,
+        .flat_map(|(family, address)| {
+            paths.iter().map(move |path| DetectionTarget {
+                stage: ProbeStage::Gateway,
+                family: Some(*family),
+                name: target_name(&format!("Gateway{}", path), named.then_some(*family)),
+                url: format!("http://{}{}", url_host(address), path),
+                expected_status: None,
+                expected_body: None,
+                allow_page_redirect: true,
+                nonexistent: false,
+            })
         })
-        .collect();
+        .collect())
+}
+
+/// Probes the well-known endpoints once, without looking up any route.
+pub fn detect_standard(ctx: &DetectionContext) -> DetectionResult {
+    detect_standard_with(ctx, &[])
+}
+
+/// Like [`detect_standard`], with a probe per family on dual-stack `gateways`.
+pub fn detect_standard_with(
+    ctx: &DetectionContext,
+    gateways: &[(AddressFamily, String)],
+) -> DetectionResult {
+    run_detection(standard_targets(ctx, gateways), ctx)
+}
 
-    run_detection(&targets, ctx)
+pub fn detect_gateway(ctx: &DetectionContext) -> DetectionResult {
+    match gateway_targets(ctx, &find_gateways(ctx)) {
+        Ok(targets) => run_detection(targets, ctx),
+        Err(result) => result,
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -496,81 +1763,154 @@ pub enum ExecutionStatus {
 
 struct Detector<'a> {
     config: &'a DetectionConfig,
+    net: Arc<dyn NetworkClient>,
     commands: &'a dyn CommandRunner,
     options: &'a Options,
     opener: &'a dyn PortalOpener,
+    wifi: &'a dyn WifiControl,
+    reporter: &'a dyn Reporter,
+    attempts: Cell<u32>,
 }
 
 impl<'a> Detector<'a> {
     fn run(&self) -> Result<ExecutionStatus, ReauthfiError> {
-        let (status, errors) = self.detect_once()?;
+        let (status, errors) = self.detect_once();
         match status {
             ExecutionStatus::Completed => Ok(ExecutionStatus::Completed),
-            ExecutionStatus::NetworkNotReady => self.retry_with_wifi_reset(errors),
+            ExecutionStatus::NetworkNotReady if self.options.wifi_reset => {
+                self.retry_with_wifi_reset(errors)
+            }
+            ExecutionStatus::NetworkNotReady => self.finish_network_not_ready(&errors),
         }
     }
 
-    fn detect_once(&self) -> Result<(ExecutionStatus, Vec<String>), ReauthfiError> {
-        let net = Arc::new(HttpClient::new(self.options.timeout)?);
+    fn detect_once(&self) -> (ExecutionStatus, Vec<String>) {
+        self.attempts.set(self.attempts.get() + 1);
+        self.reporter.report(&ReportEvent::AttemptStarted {
+            attempt: self.attempts.get(),
+        });
         let ctx = DetectionContext {
             config: self.config,
-            net: net.clone(),
+            net: self.net.clone(),
             commands: self.commands,
             options: self.options,
+            reporter: self.reporter,
         };
-        Ok(detect_portal(&ctx, self.opener))
+        let (status, errors) = detect_portal(&ctx, self.opener);
+        self.reporter.report(&ReportEvent::AttemptFinished {
+            status,
+            errors: &errors,
+        });
+        (status, errors)
     }
 
     fn retry_with_wifi_reset(
         &self,
         first_errors: Vec<String>,
     ) -> Result<ExecutionStatus, ReauthfiError> {
-        #[cfg(target_os = "macos")]
-        {
-            if self.config.supports_wifi_reset {
-                if let Ok(dev) = WifiController::wifi_device() {
-                    println!(
-                        "{} Resetting Wi-Fi on {} and retrying after reconnect...",
-                        "↻".yellow(),
-                        dev
-                    );
-                    if WifiController::reset_wifi(&dev).is_ok() {
-                        // Allow the interface time to come back up after toggle.
-                        println!("{} Waiting 10s for Wi-Fi to reconnect...", "⏳".yellow());
-                        thread::sleep(Duration::from_secs(10));
-                    }
-                    let (retry_status, retry_errors) = self.detect_once()?;
-                    if retry_status == ExecutionStatus::Completed {
-                        return Ok(ExecutionStatus::Completed);
-                    }
-                    if !retry_errors.is_empty() {
-                        return finish_network_not_ready(&retry_errors);
-                    }
+        if self.wifi.is_available() {
+            if let Ok(dev) = self.wifi.wifi_device() {
+                self.reporter
+                    .report(&ReportEvent::WifiResetStarted { device: &dev });
+                if self.wifi.reset_wifi(&dev).is_ok() {
+                    // Allow the interface time to come back up after toggle.
+                    let duration = Duration::from_secs(10);
+                    self.reporter.report(&ReportEvent::Waiting { duration });
+                    self.wifi.wait(duration);
+                }
+                let (retry_status, retry_errors) = self.detect_once();
+                if retry_status == ExecutionStatus::Completed {
+                    return Ok(ExecutionStatus::Completed);
+                }
+                if !retry_errors.is_empty() {
+                    return self.finish_network_not_ready(&retry_errors);
                 }
             }
         }
 
         if !first_errors.is_empty() {
-            finish_network_not_ready(&first_errors)
+            self.finish_network_not_ready(&first_errors)
         } else {
-            finish_network_not_ready(&[])
+            self.finish_network_not_ready(&[])
         }
     }
+
+    fn finish_network_not_ready(
+        &self,
+        errors: &[String],
+    ) -> Result<ExecutionStatus, ReauthfiError> {
+        self.reporter
+            .report(&ReportEvent::NetworkNotReady { errors });
+        Ok(ExecutionStatus::NetworkNotReady)
+    }
 }
 
 pub fn run(options: &Options) -> Result<ExecutionStatus, ReauthfiError> {
-    let config = detection_config()?;
+    run_configured(detection_config()?, options)
+}
+
+/// Like [`run`], with the terminal or JSON output chosen by `options`.
+pub fn run_configured(
+    config: &DetectionConfig,
+    options: &Options,
+) -> Result<ExecutionStatus, ReauthfiError> {
+    if !options.json {
+        let reporter = TerminalReporter::new(options.print_url).verbose(options.verbose);
+        return run_with_config(config, options, &reporter);
+    }
 
+    let reporter = JsonReporter::default();
+    let status = run_with_config(config, options, &reporter)?;
+    let document = serde_json::to_string_pretty(&reporter.into_report())
+        .map_err(|e| ReauthfiError::Setup(format!("failed to encode report: {}", e)))?;
+    println!("{}", document);
+    Ok(status)
+}
+
+/// Runs detection with the platform defaults, sending all progress to `reporter`.
+pub fn run_with_reporter(
+    options: &Options,
+    reporter: &dyn Reporter,
+) -> Result<ExecutionStatus, ReauthfiError> {
+    run_with_config(detection_config()?, options, reporter)
+}
+
+/// Finds the SSID and gateway used to pick network profiles; either may be unknown.
+pub fn identify_network(config: &DetectionConfig, commands: &dyn CommandRunner) -> NetworkIdentity {
+    let wifi = WifiController::new(config, commands);
+    NetworkIdentity {
+        ssid: wifi
+            .wifi_device()
+            .and_then(|device| wifi.ssid(&device))
+            .ok(),
+        gateway: get_gateway_ip(config, commands)
+            .or_else(|_| get_gateway_ipv6(config, commands))
+            .ok(),
+    }
+}
+
+/// Runs detection with a custom (e.g. builder-made) config.
+pub fn run_with_config(
+    config: &DetectionConfig,
+    options: &Options,
+    reporter: &dyn Reporter,
+) -> Result<ExecutionStatus, ReauthfiError> {
+    let net = Arc::new(HttpClient::new(options.timeout)?);
     let commands = SystemCommandRunner;
-    let opener = MacPortalOpener;
+    let wifi = WifiController::new(config, &commands);
+    let opener = portal_opener(options, &commands);
 
-    println!("{}", "🔍 Detecting Captive Portal...".cyan().bold());
+    reporter.report(&ReportEvent::DetectionStarted);
 
     let detector = Detector {
-        config: &config,
+        config,
+        net,
         commands: &commands,
         options,
-        opener: &opener,
+        opener: opener.as_ref(),
+        wifi: &wifi,
+        reporter,
+        attempts: Cell::new(0),
     };
 
     detector.run()
@@ -584,17 +1924,46 @@ fn detect_portal(
     let mut any_success = false;
     let mut all_errors: Vec<String> = Vec::new();
 
-    let detection_steps: [fn(&DetectionContext) -> DetectionResult; 2] =
-        [detect_standard, detect_gateway];
+    // The Captive Portal API is authoritative and costs nothing on networks that do
+    // not advertise one, so it is asked first. The standard and gateway probes and
+    // the DNS checks then all run at once.
+    let mut results: Vec<DetectionResult> = detect_captive_api(ctx).into_iter().collect();
+    if !matches!(results.first(), Some(DetectionResult::PortalFound(_))) {
+        let gateways = find_gateways(ctx);
+        let mut stages = vec![standard_targets(ctx, &gateways)];
+        let gateway = gateway_targets(ctx, &gateways);
+        stages.extend(gateway.as_ref().ok().cloned());
+        let lookups = dns::dns_targets(ctx);
+        let checks_dns = !lookups.is_empty();
+        if checks_dns {
+            stages.push(lookups);
+        }
+        let mut stage_results = run_stages(&stages, ctx);
+        // DNS answers only count as evidence: names resolving says nothing about
+        // whether HTTP gets through.
+        if checks_dns && stage_results.len() == stages.len() {
+            stage_results.pop();
+        }
+        results.extend(stage_results);
+        results.extend(gateway.err());
+    }
 
-    for detect in detection_steps {
-        match detect(ctx) {
+    for result in results {
+        match result {
             DetectionResult::PortalFound(portal_url) => {
-                println!("  {} Portal URL: {}", "→".green().bold(), portal_url);
+                ctx.reporter
+                    .report(&ReportEvent::PortalFound { url: &portal_url });
+
+                if !ctx.options.open {
+                    return (ExecutionStatus::Completed, Vec::new());
+                }
 
-                println!("{}", "📱 Opening in browser...".cyan().bold());
+                ctx.reporter
+                    .report(&ReportEvent::OpeningPortal { url: &portal_url });
                 match opener.open(&portal_url) {
-                    Ok(_) => println!("{}", "✅ Done!".green().bold()),
+                    Ok(launcher) => ctx.reporter.report(&ReportEvent::PortalOpened {
+                        launcher: &launcher,
+                    }),
                     Err(e) => return (ExecutionStatus::NetworkNotReady, vec![e.to_string()]),
                 }
                 return (ExecutionStatus::Completed, Vec::new());
@@ -603,6 +1972,13 @@ fn detect_portal(
                 saw_error = true;
                 all_errors.extend(errors);
             }
+            DetectionResult::SessionActive(status) => {
+                ctx.reporter.report(&ReportEvent::SessionRemaining {
+                    seconds: status.seconds_remaining,
+                    bytes: status.bytes_remaining,
+                });
+                any_success = true;
+            }
             DetectionResult::NoPortalDetected => {
                 any_success = true;
             }
@@ -610,29 +1986,20 @@ fn detect_portal(
     }
 
     if any_success {
-        println!("{} No captive portal detected", "✅".green().bold());
+        ctx.reporter.report(&ReportEvent::NoPortalDetected);
         (ExecutionStatus::Completed, Vec::new())
     } else if saw_error {
         (ExecutionStatus::NetworkNotReady, all_errors)
     } else {
-        println!("{} No captive portal detected", "✅".green().bold());
+        ctx.reporter.report(&ReportEvent::NoPortalDetected);
         (ExecutionStatus::Completed, Vec::new())
     }
 }
 
-fn finish_network_not_ready(errors: &[String]) -> Result<ExecutionStatus, ReauthfiError> {
-    if !errors.is_empty() {
-        let detail = errors.join(", ");
-        print_network_not_ready(Some(&detail));
-    } else {
-        print_network_not_ready(None);
-    }
-    Ok(ExecutionStatus::NetworkNotReady)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::{fake_context, RecordingOpener, Script, ScriptedRunner};
 
     struct MockRunner {
         output: String,
@@ -644,22 +2011,119 @@ mod tests {
         }
     }
 
+    /// Answers the probe of [`PROBE_ENDPOINTS`] with `statuses` in turn, with empty
+    /// bodies; redirects point at `http://portal.test/login`.
+    fn status_net(statuses: &[u16]) -> FakeNetworkClient {
+        let url = "http://probe.test/generate_204";
+        statuses
+            .iter()
+            .fold(FakeNetworkClient::new(), |net, status| {
+                net.redirect(url, *status, "http://portal.test/login")
+            })
+    }
+
+    const PROBE_ENDPOINTS: &[DetectionEndpoint] = &[DetectionEndpoint {
+        name: Cow::Borrowed("Probe"),
+        url: Cow::Borrowed("http://probe.test/generate_204"),
+        expected_status: Some(204),
+        expected_body: None,
+    }];
+
+    const NETWORKSETUP_PORTS: &str = "Hardware Port: Wi-Fi\nDevice: en1\n";
+
+    struct WifiRun {
+        status: ExecutionStatus,
+        commands: Vec<String>,
+        waits: Vec<u64>,
+        unused_responses: usize,
+        opened: Vec<String>,
+        report: DetectionReport,
+    }
+
+    /// Runs a full detection with the Wi-Fi reset backed by `script`; gateway lookups
+    /// always fail, so only the scripted probe statuses decide each attempt.
+    fn run_with_wifi(script: Script, available: bool, statuses: &[u16]) -> WifiRun {
+        run_with_options(script, available, statuses, Options::default())
+    }
+
+    fn run_with_options(
+        script: Script,
+        available: bool,
+        statuses: &[u16],
+        options: Options,
+    ) -> WifiRun {
+        let config = DetectionConfig {
+            detection_endpoints: Cow::Borrowed(PROBE_ENDPOINTS),
+            wifi_backend: WifiBackend::NetworkSetup,
+            ..dummy_config()
+        };
+        let runner = ScriptedRunner::new(script);
+        let waits = std::sync::Mutex::new(Vec::new());
+        let record_wait = |d: Duration| waits.lock().unwrap().push(d.as_secs());
+        let wifi = WifiController {
+            backend: config.wifi_backend,
+            commands: &runner,
+            available,
+            sleep: &record_wait,
+        };
+        let net = Arc::new(status_net(statuses));
+        let opener = RecordingOpener::default();
+        let reporter = JsonReporter::default();
+        let detector = Detector {
+            config: &config,
+            net: net.clone(),
+            commands: &runner,
+            options: &options,
+            opener: &opener,
+            wifi: &wifi,
+            reporter: &reporter,
+            attempts: Cell::new(0),
+        };
+
+        let status = detector.run().unwrap();
+        let commands = runner
+            .calls()
+            .into_iter()
+            .filter(|cmd| cmd.starts_with("networksetup"))
+            .collect();
+        let waits = waits.lock().unwrap().clone();
+        let opened = opener.opened.lock().unwrap().clone();
+        let report = reporter.into_report();
+        WifiRun {
+            status,
+            report,
+            commands,
+            waits,
+            unused_responses: statuses.len().saturating_sub(net.requests().len()),
+            opened,
+        }
+    }
+
     fn dummy_config() -> DetectionConfig {
         DetectionConfig {
-            detection_endpoints: &[],
+            detection_endpoints: Cow::Borrowed(&[]),
             gateway_command: &["route"],
             gateway_regex: MACOS_GATEWAY_REGEX,
-            gateway_endpoints: &[],
-            supports_wifi_reset: true,
+            gateway_route_file: None,
+            gateway6_command: &["route", "-n", "get", "-inet6", "default"],
+            gateway6_regex: MACOS_GATEWAY6_REGEX,
+            gateway_endpoints: Cow::Borrowed(&[]),
+            gateway_address: None,
+            wifi_backend: WifiBackend::None,
+            dhcp_leases: DhcpLeaseSource::None,
         }
     }
 
     fn base_target() -> DetectionTarget {
         DetectionTarget {
+            stage: ProbeStage::Standard,
+            family: None,
             name: "Test".to_string(),
             url: "http://example.com".to_string(),
             expected_status: None,
-            allow_meta_refresh: false,
+            expected_body: None,
+            allow_page_redirect: false,
+            nonexistent: false,
         }
     }
 
@@ -685,6 +2149,59 @@ mod tests {
         assert!(matches!(err, ReauthfiError::NotFound));
     }
 
+    #[test]
+    fn gateway_ip_is_parsed_from_ip_route_output() {
+        let cfg = DetectionConfig {
+            gateway_regex: LINUX_GATEWAY_REGEX,
+            ..dummy_config()
+        };
+        let runner = MockRunner {
+            output: "default via 192.168.1.1 dev wlp2s0 proto dhcp metric 600\n".to_string(),
+        };
+
+        let ip = get_gateway_ip(&cfg, &runner).unwrap();
+        assert_eq!(ip, "192.168.1.1");
+    }
+
+    #[test]
+    fn gateway_ip_is_parsed_from_proc_net_route() {
+        let table = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+wlan0\t0000A8C0\t00000000\t0001\t0\t0\t600\t00FFFFFF\t0\t0\t0
+wlan0\t00000000\t0101A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0
+";
+
+        assert_eq!(parse_proc_net_route(table).as_deref(), Some("192.168.1.1"));
+        assert_eq!(parse_proc_net_route(table.lines().next().unwrap()), None);
+    }
+
+    #[test]
+    fn ipv6_gateway_keeps_the_zone_of_link_local_routers() {
+        let macos =
+            "   route to: ::\ndestination: ::\n    gateway: fe80::1%en0\n  interface: en0\n";
+        assert_eq!(
+            parse_gateway6_output(MACOS_GATEWAY6_REGEX, macos).as_deref(),
+            Some("fe80::1%en0")
+        );
+
+        let linux = "default via fe80::1 dev wlan0 proto ra metric 600 pref medium\n";
+        assert_eq!(
+            parse_gateway6_output(LINUX_GATEWAY6_REGEX, linux).as_deref(),
+            Some("fe80::1%wlan0")
+        );
+
+        let global = "default via 2001:db8::1 dev eth0 metric 1024\n";
+        assert_eq!(
+            parse_gateway6_output(LINUX_GATEWAY6_REGEX, global).as_deref(),
+            Some("2001:db8::1")
+        );
+
+        assert_eq!(
+            parse_gateway6_output(MACOS_GATEWAY6_REGEX, "gateway: 10.0.0.1"),
+            None
+        );
+    }
+
     #[test]
     fn classify_prefers_redirect_location() {
         let target = base_target();
@@ -703,16 +2220,1113 @@ mod tests {
     #[test]
     fn classify_detects_meta_refresh() {
         let mut target = base_target();
-        target.allow_meta_refresh = true;
+        target.allow_page_redirect = true;
         let body = r#"<html><meta http-equiv="refresh" content="0; url=http://portal"/></html>"#;
         let outcome = classify_parts(&target, 200, None, Some(body.to_string()));
         assert!(matches!(outcome, Outcome::Portal(url) if url == "http://portal"));
     }
 
     #[test]
-    fn classify_accepts_success_body() {
+    fn classify_511_uses_link_header_then_body() {
         let target = base_target();
-        let outcome = classify_parts(&target, 200, None, Some("Success".to_string()));
+        let body = r#"<p>Please <a href="https://login.example/auth">log in</a></p>"#;
+
+        let outcome = classify_authentication_required(
+            &target,
+            Some("https://link.example/login".to_string()),
+            Some(body),
+        );
+        assert!(
+            matches!(outcome, Outcome::AuthenticationRequired(url) if url == "https://link.example/login")
+        );
+
+        let outcome = classify_authentication_required(&target, None, Some(body));
+        assert!(
+            matches!(outcome, Outcome::AuthenticationRequired(url) if url == "https://login.example/auth")
+        );
+
+        let outcome = classify_authentication_required(&target, None, Some("<p>Log in</p>"));
+        assert!(
+            matches!(outcome, Outcome::AuthenticationRequired(url) if url == "http://example.com")
+        );
+    }
+
+    #[test]
+    fn cancelled_probes_send_nothing() {
+        let net = FakeNetworkClient::new().redirect("http://example.com", 302, "/login");
+        let cancel = AtomicBool::new(true);
+
+        assert!(probe(&net, &base_target(), Duration::from_secs(1), 5, &cancel).is_none());
+        assert!(net.requests().is_empty());
+    }
+
+    #[test]
+    fn link_header_prefers_captive_portal_relations() {
+        let link = |values: &[&str]| {
+            let response = values.iter().fold(
+                ProbeResponse::new(511, "http://example.com"),
+                |response, value| response.header("link", *value),
+            );
+            link_header_url(&response)
+        };
+
+        assert_eq!(
+            link(&[
+                r#"<https://portal.example/style.css>; rel="stylesheet""#,
+                r#"<https://portal.example/login>; rel="login", <https://portal.example/capport>; rel="captive-portal""#,
+            ])
+            .as_deref(),
+            Some("https://portal.example/capport")
+        );
+        assert_eq!(
+            link(&[r#"<https://portal.example/app.js>; rel=preload; as=script"#]),
+            None
+        );
+        assert_eq!(
+            link(&[r#"<https://portal.example/login>; rel="nofollow login""#]).as_deref(),
+            Some("https://portal.example/login")
+        );
+    }
+
+    #[test]
+    fn status_511_is_reported_as_portal() {
+        let response = ProbeResponse::new(511, "http://example.com")
+            .header("link", r#"<https://portal.example/login>; rel="login""#)
+            .body("<html>Network Authentication Required</html>");
+
+        let outcome = classify_response(&base_target(), response);
+        assert!(
+            matches!(outcome, Outcome::AuthenticationRequired(url) if url == "https://portal.example/login")
+        );
+    }
+
+    #[test]
+    fn classify_treats_content_on_generate_204_as_portal() {
+        let mut target = base_target();
+        target.expected_status = Some(204);
+
+        let js = r#"<script>window.location.href = "http://portal.example/js";</script>"#;
+        let outcome = classify_parts(&target, 200, None, Some(js.to_string()));
+        assert!(matches!(outcome, Outcome::Portal(url) if url == "http://portal.example/js"));
+
+        let form = r#"<form method="post" action="https://portal.example/auth"><input></form>"#;
+        let outcome = classify_parts(&target, 200, None, Some(form.to_string()));
+        assert!(matches!(outcome, Outcome::Portal(url) if url == "https://portal.example/auth"));
+
+        let plain = "<html><body>Welcome to Hotel Wi-Fi</body></html>";
+        let outcome = classify_parts(&target, 200, None, Some(plain.to_string()));
+        assert!(matches!(outcome, Outcome::Portal(url) if url == "http://example.com"));
+
+        let outcome = classify_parts(&target, 200, None, Some(String::new()));
+        assert!(matches!(outcome, Outcome::Mismatch(200)));
+    }
+
+    #[test]
+    fn apple_requires_the_exact_success_page() {
+        let mut target = base_target();
+        target.expected_body = STANDARD_DETECTION_ENDPOINTS[0].expected_body.clone();
+
+        let page = "<HTML><HEAD><TITLE>Success</TITLE></HEAD><BODY>Success</BODY></HTML>\n";
+        let outcome = classify_parts(&target, 200, None, Some(page.to_string()));
+        assert!(matches!(outcome, Outcome::ExpectedOk));
+
+        let portal = "<html><body>Login success required</body></html>";
+        let outcome = classify_parts(&target, 200, None, Some(portal.to_string()));
+        assert!(matches!(outcome, Outcome::BodyMismatch));
+
+        let portal = r#"<html><form action="https://portal.example/login">Login success required</form></html>"#;
+        let outcome = classify_parts(&target, 200, None, Some(portal.to_string()));
+        assert!(matches!(outcome, Outcome::Portal(url) if url == "https://portal.example/login"));
+    }
+
+    #[test]
+    fn expected_body_regex_is_matched() {
+        let mut target = base_target();
+        target.expected_status = Some(200);
+        target.expected_body = Some(BodyMatch::regex("^pong").unwrap());
+
+        let outcome = classify_parts(&target, 200, None, Some("pong 42".to_string()));
         assert!(matches!(outcome, Outcome::ExpectedOk));
+        let outcome = classify_parts(&target, 200, None, Some("ping".to_string()));
+        assert!(matches!(outcome, Outcome::BodyMismatch));
+    }
+
+    #[test]
+    fn classify_needs_an_expectation_to_accept_a_page() {
+        let target = base_target();
+        let outcome = classify_parts(&target, 200, None, Some("Success".to_string()));
+        assert!(matches!(outcome, Outcome::Mismatch(200)));
+    }
+
+    #[test]
+    fn xdg_opener_prefers_xdg_open() {
+        let runner = ScriptedRunner::new(&[("xdg-open", Ok("")), ("gio", Ok(""))]);
+        let opener = XdgPortalOpener {
+            commands: &runner,
+            browser_env: None,
+        };
+
+        let used = opener.open("http://portal").unwrap();
+        assert_eq!(used, "xdg-open");
+        assert_eq!(runner.calls(), vec!["xdg-open http://portal"]);
+    }
+
+    #[test]
+    fn xdg_launchers_run_with_stdio_detached() {
+        let runner = ScriptedRunner::new(&[("xdg-open", Err("no handler")), ("gio", Ok(""))]);
+        let opener = XdgPortalOpener {
+            commands: &runner,
+            browser_env: None,
+        };
+
+        let used = opener.open("http://portal").unwrap();
+        assert_eq!(used, "gio open");
+        assert_eq!(
+            runner.detached(),
+            vec!["xdg-open http://portal", "gio open http://portal"]
+        );
+    }
+
+    #[test]
+    fn xdg_opener_falls_back_to_browser_env() {
+        let runner = ScriptedRunner::new(&[("w3m", Ok(""))]);
+        let opener = XdgPortalOpener {
+            commands: &runner,
+            browser_env: Some("missing-browser:w3m -o %s".to_string()),
+        };
+
+        let used = opener.open("http://portal").unwrap();
+        assert_eq!(used, "$BROWSER (w3m)");
+        assert_eq!(
+            runner.calls(),
+            vec![
+                "xdg-open http://portal",
+                "gio open http://portal",
+                "missing-browser http://portal",
+                "w3m -o http://portal",
+            ]
+        );
+    }
+
+    #[test]
+    fn xdg_opener_reports_every_failed_launcher() {
+        let runner = ScriptedRunner::new(&[]);
+        let opener = XdgPortalOpener {
+            commands: &runner,
+            browser_env: None,
+        };
+
+        let err = opener.open("http://portal").unwrap_err();
+        assert!(matches!(err, ReauthfiError::CommandFailed(msg) if msg.contains("firefox")));
+        assert_eq!(runner.calls().len(), 2 + KNOWN_BROWSERS.len());
+    }
+
+    #[test]
+    fn wifi_device_is_parsed_from_networksetup() {
+        let stdout = "\
+Hardware Port: Ethernet
+Device: en0
+Ethernet Address: aa:bb:cc:dd:ee:ff
+
+Hardware Port: Wi-Fi
+Device: en1
+Ethernet Address: 11:22:33:44:55:66
+";
+        assert_eq!(parse_networksetup_device(stdout).as_deref(), Some("en1"));
+    }
+
+    #[test]
+    fn wifi_device_is_parsed_from_nmcli_and_iw() {
+        let nmcli = "enp3s0:ethernet\nwlp2s0:wifi\nlo:loopback\n";
+        assert_eq!(parse_nmcli_device(nmcli).as_deref(), Some("wlp2s0"));
+        assert_eq!(parse_nmcli_device("enp3s0:ethernet\n"), None);
+
+        let iw = "phy#0\n\tInterface wlan0\n\t\tifindex 3\n\t\ttype managed\n";
+        assert_eq!(parse_iw_device(iw).as_deref(), Some("wlan0"));
+    }
+
+    #[test]
+    fn network_not_ready_resets_wifi_and_retries() {
+        let run = run_with_wifi(
+            &[
+                ("networksetup -listallhardwareports", Ok(NETWORKSETUP_PORTS)),
+                ("networksetup -setairportpower", Ok("")),
+            ],
+            true,
+            &[500, 204],
+        );
+
+        assert_eq!(run.status, ExecutionStatus::Completed);
+        assert_eq!(
+            run.commands,
+            vec![
+                "networksetup -listallhardwareports",
+                "networksetup -setairportpower en1 off",
+                "networksetup -setairportpower en1 on",
+            ]
+        );
+        assert_eq!(run.waits, vec![2, 10]);
+        assert_eq!(run.unused_responses, 0);
+    }
+
+    #[test]
+    fn failed_power_off_skips_power_on_but_still_retries() {
+        let run = run_with_wifi(
+            &[
+                ("networksetup -listallhardwareports", Ok(NETWORKSETUP_PORTS)),
+                (
+                    "networksetup -setairportpower en1 off",
+                    Err("not permitted"),
+                ),
+                ("networksetup -setairportpower en1 on", Ok("")),
+            ],
+            true,
+            &[500, 500],
+        );
+
+        assert_eq!(run.status, ExecutionStatus::NetworkNotReady);
+        assert_eq!(
+            run.commands.last().map(String::as_str),
+            Some("networksetup -setairportpower en1 off")
+        );
+        assert!(run.waits.is_empty());
+        assert_eq!(run.unused_responses, 0);
+    }
+
+    #[test]
+    fn failed_power_on_skips_reconnect_wait() {
+        let run = run_with_wifi(
+            &[
+                ("networksetup -listallhardwareports", Ok(NETWORKSETUP_PORTS)),
+                ("networksetup -setairportpower en1 off", Ok("")),
+                ("networksetup -setairportpower en1 on", Err("device busy")),
+            ],
+            true,
+            &[500, 204],
+        );
+
+        assert_eq!(run.status, ExecutionStatus::Completed);
+        assert_eq!(run.commands.len(), 3);
+        assert_eq!(run.waits, vec![2]);
+    }
+
+    #[test]
+    fn unavailable_wifi_reset_does_not_retry() {
+        let run = run_with_wifi(&[], false, &[500, 204]);
+
+        assert_eq!(run.status, ExecutionStatus::NetworkNotReady);
+        assert!(run.commands.is_empty());
+        assert_eq!(run.unused_responses, 1);
+    }
+
+    #[test]
+    fn network_manager_falls_back_to_rfkill() {
+        let runner = ScriptedRunner::new(&[("nmcli", Err("Not authorized")), ("rfkill", Ok(""))]);
+        let wifi = WifiController {
+            backend: WifiBackend::NetworkManager,
+            commands: &runner,
+            available: true,
+            sleep: &|_| {},
+        };
+
+        wifi.reset_wifi("wlan0").unwrap();
+        assert_eq!(
+            runner.calls(),
+            vec![
+                "nmcli radio wifi off",
+                "nmcli device disconnect wlan0",
+                "rfkill block wifi",
+                "rfkill unblock wifi",
+            ]
+        );
+    }
+
+    #[test]
+    fn network_manager_turns_wifi_back_on_when_radio_on_fails() {
+        let runner = ScriptedRunner::new(&[
+            ("nmcli radio wifi on", Err("Not authorized")),
+            ("nmcli device connect", Err("Not authorized")),
+            ("nmcli radio wifi off", Ok("")),
+            ("rfkill unblock wifi", Ok("")),
+        ]);
+        let wifi = WifiController {
+            backend: WifiBackend::NetworkManager,
+            commands: &runner,
+            available: true,
+            sleep: &|_| {},
+        };
+
+        wifi.reset_wifi("wlan0").unwrap();
+        assert_eq!(
+            runner.calls(),
+            vec![
+                "nmcli radio wifi off",
+                "nmcli radio wifi on",
+                "nmcli radio wifi on",
+                "nmcli device connect wlan0",
+                "rfkill unblock wifi",
+            ]
+        );
+    }
+
+    #[test]
+    fn portal_is_opened_by_default() {
+        let run = run_with_wifi(&[], false, &[302]);
+
+        assert_eq!(run.status, ExecutionStatus::Completed);
+        assert_eq!(run.opened, vec!["http://portal.test/login"]);
+    }
+
+    #[test]
+    fn no_open_detects_without_opening() {
+        let options = Options {
+            open: false,
+            print_url: true,
+            ..Options::default()
+        };
+        let run = run_with_options(&[], false, &[302], options);
+
+        assert_eq!(run.status, ExecutionStatus::Completed);
+        assert!(run.opened.is_empty());
+    }
+
+    #[test]
+    fn no_wifi_reset_skips_reset_even_when_available() {
+        let options = Options {
+            wifi_reset: false,
+            ..Options::default()
+        };
+        let run = run_with_options(
+            &[("networksetup", Ok(NETWORKSETUP_PORTS))],
+            true,
+            &[500, 204],
+            options,
+        );
+
+        assert_eq!(run.status, ExecutionStatus::NetworkNotReady);
+        assert!(run.commands.is_empty());
+        assert_eq!(run.unused_responses, 1);
+    }
+
+    #[test]
+    fn report_records_probes_and_finder() {
+        let run = run_with_wifi(&[], false, &[302]);
+
+        let report = run.report;
+        assert_eq!(report.status, ReportStatus::PortalFound);
+        assert_eq!(
+            report.portal_url.as_deref(),
+            Some("http://portal.test/login")
+        );
+        assert_eq!(report.opened_with.as_deref(), Some("test"));
+        let found_by = report.found_by.unwrap();
+        assert_eq!(found_by.stage, ProbeStage::Standard);
+        assert_eq!(found_by.endpoint, "Probe");
+        assert_eq!(report.probes.len(), 1);
+        assert_eq!(report.probes[0].status_code, Some(302));
+        assert_eq!(report.probes[0].outcome, ProbeOutcome::Portal);
+    }
+
+    #[test]
+    fn report_keeps_errors_across_wifi_reset() {
+        let run = run_with_wifi(
+            &[
+                ("networksetup -listallhardwareports", Ok(NETWORKSETUP_PORTS)),
+                ("networksetup -setairportpower", Ok("")),
+            ],
+            true,
+            &[500, 500],
+        );
+
+        let report = run.report;
+        assert_eq!(report.status, ReportStatus::NetworkNotReady);
+        assert_eq!(report.attempts, 2);
+        assert!(report.wifi_reset);
+        assert_eq!(report.errors, vec!["Probe: status 500", "gateway_ip"]);
+        assert_eq!(
+            report.probes.iter().map(|p| p.attempt).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["status"], "network_not_ready");
+        assert_eq!(json["probes"][0]["outcome"], "mismatch");
+        assert_eq!(json["probes"][0]["stage"], "standard");
+    }
+
+    #[test]
+    fn report_distinguishes_a_failed_open() {
+        let reporter = JsonReporter::default();
+        let errors = vec!["no browser launcher succeeded".to_string()];
+        reporter.report(&ReportEvent::AttemptStarted { attempt: 1 });
+        reporter.report(&ReportEvent::PortalFound {
+            url: "http://portal.test/login",
+        });
+        reporter.report(&ReportEvent::AttemptFinished {
+            status: ExecutionStatus::NetworkNotReady,
+            errors: &errors,
+        });
+
+        let report = reporter.into_report();
+        assert_eq!(report.status, ReportStatus::OpenFailed);
+        assert_eq!(
+            report.portal_url.as_deref(),
+            Some("http://portal.test/login")
+        );
+        assert_eq!(
+            serde_json::to_value(&report).unwrap()["status"],
+            "open_failed"
+        );
+    }
+
+    struct EventLog {
+        events: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl Reporter for EventLog {
+        fn report(&self, event: &ReportEvent) {
+            let name = format!("{:?}", event);
+            let name = name.split([' ', '{', '(']).next().unwrap().to_string();
+            self.events.lock().unwrap().push(name);
+        }
+    }
+
+    #[test]
+    fn detection_events_are_sent_to_the_reporter() {
+        let config = DetectionConfig {
+            detection_endpoints: Cow::Borrowed(PROBE_ENDPOINTS),
+            ..dummy_config()
+        };
+        let runner = ScriptedRunner::new(&[]);
+        let options = Options::default();
+        let log = EventLog {
+            events: std::sync::Mutex::new(Vec::new()),
+        };
+        let ctx = fake_context(&config, status_net(&[302]), &runner, &options, &log);
+        let opener = RecordingOpener::default();
+
+        let (status, _) = detect_portal(&ctx, &opener);
+
+        assert_eq!(status, ExecutionStatus::Completed);
+        assert_eq!(
+            *log.events.lock().unwrap(),
+            vec![
+                "StageStarted",
+                "ProbeStarted",
+                "ProbeOutcome",
+                "Verdict",
+                "PortalFound",
+                "OpeningPortal",
+                "PortalOpened",
+            ]
+        );
+    }
+
+    #[test]
+    fn followed_redirects_open_the_landing_page() {
+        let config = crate::testing::probe_config();
+        let net = FakeNetworkClient::new()
+            .redirect(
+                "http://probe.test/generate_204",
+                302,
+                "http://1.1.1.1/redirect?id=7",
+            )
+            .redirect(
+                "http://1.1.1.1/redirect?id=7",
+                302,
+                "http://portal.test/splash",
+            )
+            .page(
+                "http://portal.test/splash",
+                200,
+                "<form action=\"/login\"></form>",
+            );
+        let runner = ScriptedRunner::new(&[]);
+        let options = Options {
+            follow_redirects: 5,
+            ..Options::default()
+        };
+        let reporter = JsonReporter::default();
+        let ctx = fake_context(&config, net, &runner, &options, &reporter);
+        let opener = RecordingOpener::default();
+
+        detect_portal(&ctx, &opener);
+
+        assert_eq!(opener.opened(), vec!["http://portal.test/splash"]);
+        let report = reporter.into_report();
+        let hops: Vec<(&str, Option<u16>)> = report.probes[0]
+            .redirects
+            .iter()
+            .map(|hop| (hop.url.as_str(), hop.status_code))
+            .collect();
+        assert_eq!(
+            hops,
+            [
+                ("http://probe.test/generate_204", Some(302)),
+                ("http://1.1.1.1/redirect?id=7", Some(302)),
+                ("http://portal.test/splash", Some(200)),
+            ]
+        );
+        assert_eq!(
+            report.probes[0].detail.as_deref(),
+            Some("http://portal.test/splash")
+        );
+    }
+
+    #[test]
+    fn first_portal_wins_without_waiting_for_slow_probes() {
+        let config = DetectionConfig {
+            detection_endpoints: Cow::Owned(vec![
+                DetectionEndpoint::new("Slow", "http://slow.test/", Some(204)),
+                DetectionEndpoint::new("Fast", "http://fast.test/", Some(204)),
+            ]),
+            gateway_endpoints: Cow::Owned(vec![Cow::Borrowed("/")]),
+            ..dummy_config()
+        };
+        let runner = ScriptedRunner::new(&[("route -n get default", Ok("gateway: 10.0.0.1"))]);
+        let options = Options::default();
+        let net = FakeNetworkClient::new()
+            .redirect("http://fast.test/", 302, "http://portal.test/login")
+            .respond(
+                "http://slow.test/",
+                ProbeResponse::new(204, "http://slow.test/"),
+            )
+            .respond(
+                "http://10.0.0.1/",
+                ProbeResponse::new(204, "http://10.0.0.1/"),
+            )
+            .delay("http://slow.test/", Duration::from_secs(5))
+            .delay("http://10.0.0.1/", Duration::from_secs(5));
+        let ctx = fake_context(&config, net, &runner, &options, &SilentReporter);
+        let opener = RecordingOpener::default();
+
+        let started = Instant::now();
+        let (status, _) = detect_portal(&ctx, &opener);
+
+        assert_eq!(status, ExecutionStatus::Completed);
+        assert_eq!(opener.opened(), vec!["http://portal.test/login"]);
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn stage_results_are_aggregated_in_order() {
+        let config = DetectionConfig {
+            detection_endpoints: Cow::Owned(vec![
+                DetectionEndpoint::new("A", "http://a.test/", Some(200)),
+                DetectionEndpoint::new("B", "http://b.test/", Some(200)),
+            ]),
+            ..dummy_config()
+        };
+        let runner = ScriptedRunner::new(&[]);
+        let options = Options::default();
+        let net = FakeNetworkClient::new().otherwise(204);
+        let ctx = fake_context(&config, net, &runner, &options, &SilentReporter);
+
+        let (status, errors) = detect_portal(&ctx, &RecordingOpener::default());
+
+        assert_eq!(status, ExecutionStatus::NetworkNotReady);
+        assert_eq!(errors, ["A: status 204", "B: status 204", "gateway_ip"]);
+    }
+
+    #[test]
+    fn all_must_pass_catches_a_walled_garden() {
+        let config = DetectionConfig {
+            detection_endpoints: Cow::Owned(vec![
+                DetectionEndpoint::new("Open", "http://open.test/", Some(204)),
+                DetectionEndpoint::new("Walled", "http://walled.test/", Some(200))
+                    .expected_body(BodyMatch::Exact("ok".into())),
+            ]),
+            ..dummy_config()
+        };
+        let runner = ScriptedRunner::new(&[]);
+        let detect = |verdict| {
+            let options = Options {
+                verdict,
+                ..Options::default()
+            };
+            let reporter = JsonReporter::default();
+            let net = FakeNetworkClient::new()
+                .page(
+                    "http://walled.test/",
+                    200,
+                    "<html>Welcome, please see the front desk</html>",
+                )
+                .otherwise(204);
+            let ctx = fake_context(&config, net, &runner, &options, &reporter);
+            let opener = RecordingOpener::default();
+            detect_portal(&ctx, &opener);
+            (opener.opened(), reporter.into_report().verdict.unwrap())
+        };
+
+        let (opened, verdict) = detect(VerdictStrategy::AnyPortal);
+        assert!(opened.is_empty());
+        assert!(!verdict.captive);
+
+        let (opened, verdict) = detect(VerdictStrategy::AllMustPass);
+        assert_eq!(opened, vec!["http://walled.test/"]);
+        assert!(verdict.captive);
+        let kinds: Vec<EvidenceKind> = verdict.evidence.iter().map(|e| e.kind).collect();
+        assert!(kinds.contains(&EvidenceKind::Passed));
+        assert!(kinds.contains(&EvidenceKind::BodyMismatch));
+    }
+
+    #[test]
+    fn standard_detection_runs_offline_on_scripted_answers() {
+        let config = DetectionConfig {
+            detection_endpoints: Cow::Borrowed(PROBE_ENDPOINTS),
+            ..dummy_config()
+        };
+        let runner = ScriptedRunner::new(&[]);
+        let options = Options::default();
+        let url = "http://probe.test/generate_204";
+
+        let net = FakeNetworkClient::new().redirect(url, 302, "/login");
+        let ctx = fake_context(&config, net, &runner, &options, &SilentReporter);
+        assert_eq!(
+            detect_standard(&ctx),
+            DetectionResult::PortalFound("http://probe.test/login".to_string())
+        );
+
+        let net = FakeNetworkClient::new().respond(url, ProbeResponse::new(204, url));
+        let ctx = fake_context(&config, net, &runner, &options, &SilentReporter);
+        assert_eq!(detect_standard(&ctx), DetectionResult::NoPortalDetected);
+
+        let net = FakeNetworkClient::new().fail(url, ProbeError::Timeout);
+        let ctx = fake_context(&config, net, &runner, &options, &SilentReporter);
+        assert_eq!(
+            detect_standard(&ctx),
+            DetectionResult::NetworkIssues(vec!["Probe: timeout (5s)".to_string()])
+        );
+    }
+
+    #[test]
+    fn standard_detection_leaves_the_routes_alone() {
+        let config = DetectionConfig {
+            detection_endpoints: Cow::Borrowed(PROBE_ENDPOINTS),
+            ..dummy_config()
+        };
+        let runner = ScriptedRunner::new(&[("route", Ok("gateway: 10.0.0.1"))]);
+        let options = Options::default();
+        let reporter = JsonReporter::default();
+        let url = "http://probe.test/generate_204";
+        let net = FakeNetworkClient::new().respond(url, ProbeResponse::new(204, url));
+        let ctx = fake_context(&config, net, &runner, &options, &reporter);
+
+        assert_eq!(detect_standard(&ctx), DetectionResult::NoPortalDetected);
+        assert!(runner.calls().is_empty());
+        assert!(reporter.into_report().families.is_empty());
+    }
+
+    #[test]
+    fn gateway_detection_follows_a_refresh_page() {
+        let config = DetectionConfig {
+            gateway_endpoints: Cow::Owned(vec![Cow::Borrowed("/"), Cow::Borrowed("/login")]),
+            ..dummy_config()
+        };
+        let runner = ScriptedRunner::new(&[("route", Ok("gateway: 10.0.0.1"))]);
+        let options = Options::default();
+        let net = FakeNetworkClient::new()
+            .page(
+                "http://10.0.0.1/",
+                200,
+                r#"<meta http-equiv="refresh" content="0; url=/portal/splash">"#,
+            )
+            .otherwise(404);
+        let ctx = fake_context(&config, net, &runner, &options, &SilentReporter);
+
+        assert_eq!(
+            detect_gateway(&ctx),
+            DetectionResult::PortalFound("http://10.0.0.1/portal/splash".to_string())
+        );
+    }
+
+    #[test]
+    fn tls_failures_are_weighed_as_evidence() {
+        let config = DetectionConfig {
+            detection_endpoints: Cow::Owned(vec![DetectionEndpoint::new(
+                "Secure",
+                "https://secure.test/",
+                Some(200),
+            )]),
+            ..dummy_config()
+        };
+        let runner = ScriptedRunner::new(&[]);
+        let options = Options {
+            verdict: VerdictStrategy::AllMustPass,
+            ..Options::default()
+        };
+        let reporter = JsonReporter::default();
+        let net = FakeNetworkClient::new().fail(
+            "https://secure.test/",
+            ProbeError::Tls("certificate is not valid for secure.test".to_string()),
+        );
+        let ctx = fake_context(&config, net, &runner, &options, &reporter);
+
+        let result = detect_standard(&ctx);
+
+        assert_eq!(
+            result,
+            DetectionResult::PortalFound("https://secure.test/".to_string())
+        );
+        let verdict = reporter.into_report().verdict.unwrap();
+        assert_eq!(verdict.evidence[0].kind, EvidenceKind::TlsAnomaly);
+    }
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    /// Runs full detection against the single probe of `PROBE_ENDPOINTS` with the
+    /// DNS checks on, returning the status, what was opened and the report.
+    fn detect_dns(
+        net: FakeNetworkClient,
+        options: Options,
+    ) -> (ExecutionStatus, Vec<String>, DetectionReport) {
+        let config = DetectionConfig {
+            detection_endpoints: Cow::Borrowed(PROBE_ENDPOINTS),
+            ..dummy_config()
+        };
+        let runner = ScriptedRunner::new(&[]);
+        let reporter = JsonReporter::default();
+        let ctx = fake_context(&config, net, &runner, &options, &reporter);
+        let opener = RecordingOpener::default();
+
+        let (status, _) = detect_portal(&ctx, &opener);
+        (status, opener.opened(), reporter.into_report())
+    }
+
+    const DNS_PROBE: &str = "http://probe.test/generate_204";
+
+    #[test]
+    fn dns_hijacks_lead_to_the_portal_at_the_answered_address() {
+        let net = FakeNetworkClient::new()
+            .fail(DNS_PROBE, ProbeError::Connect)
+            .redirect("http://10.0.0.1/", 302, "/login")
+            .with_resolver(StubResolver::new().answer("probe.test", &[ip("10.0.0.1")]));
+
+        let (status, opened, report) = detect_dns(net, Options::default());
+
+        assert_eq!(status, ExecutionStatus::Completed);
+        assert_eq!(opened, vec!["http://10.0.0.1/login"]);
+        let evidence = report.verdict.unwrap().evidence;
+        let dns: Vec<_> = evidence
+            .iter()
+            .filter(|e| e.kind == EvidenceKind::DnsAnomaly)
+            .map(|e| (e.stage, e.url.as_str()))
+            .collect();
+        assert_eq!(dns, vec![(ProbeStage::Dns, "probe.test")]);
+    }
+
+    #[test]
+    fn dns_hijacks_never_outweigh_a_passing_probe() {
+        let net = FakeNetworkClient::new()
+            .respond(DNS_PROBE, ProbeResponse::new(204, DNS_PROBE))
+            .redirect("http://10.0.0.1/", 302, "/login")
+            .delay(DNS_PROBE, Duration::from_millis(100))
+            .with_resolver(StubResolver::new().otherwise(&[ip("10.0.0.1")]));
+
+        let (status, opened, report) = detect_dns(net, Options::default());
+
+        assert_eq!(status, ExecutionStatus::Completed);
+        assert!(opened.is_empty());
+        let verdict = report.verdict.unwrap();
+        assert!(!verdict.captive);
+        assert!(verdict
+            .evidence
+            .iter()
+            .any(|e| e.kind == EvidenceKind::DnsAnomaly));
+    }
+
+    #[test]
+    fn dns_hijacks_without_a_portal_are_only_evidence() {
+        let net = FakeNetworkClient::new()
+            .fail(DNS_PROBE, ProbeError::Connect)
+            .with_resolver(StubResolver::new().otherwise(&[ip("10.0.0.1")]));
+
+        let (_, opened, report) = detect_dns(net, Options::default());
+
+        assert!(opened.is_empty());
+        let evidence = report.verdict.unwrap().evidence;
+        let dns: Vec<_> = evidence
+            .iter()
+            .filter(|e| e.kind == EvidenceKind::DnsAnomaly)
+            .map(|e| e.portal_url.as_deref())
+            .collect();
+        assert_eq!(dns, vec![None, None]);
+    }
+
+    #[test]
+    fn dns_hijacks_alone_never_open_a_looked_up_name() {
+        let net = FakeNetworkClient::new()
+            .fail(DNS_PROBE, ProbeError::Connect)
+            .with_resolver(StubResolver::new().otherwise(&[ip("10.0.0.1")]));
+        let options = Options {
+            verdict: VerdictStrategy::AllMustPass,
+            ..Options::default()
+        };
+
+        let (_, opened, report) = detect_dns(net, options);
+
+        assert!(opened.is_empty());
+        let verdict = report.verdict.unwrap();
+        assert!(verdict.captive);
+        assert_eq!(verdict.portal_url, None);
+    }
+
+    #[test]
+    fn any_answer_for_a_made_up_name_is_a_hijack() {
+        // A Cisco WLC answers every name with its public virtual address.
+        let net = || {
+            FakeNetworkClient::new()
+                .fail(DNS_PROBE, ProbeError::Connect)
+                .redirect("http://1.1.1.1/", 302, "/login.html")
+                .with_resolver(
+                    StubResolver::new()
+                        .answer("probe.test", &[ip("17.253.144.10")])
+                        .otherwise(&[ip("1.1.1.1")]),
+                )
+        };
+
+        let (_, opened, _) = detect_dns(net(), Options::default());
+        assert_eq!(opened, vec!["http://1.1.1.1/login.html"]);
+
+        // Left alone for resolvers that answer unknown names with ads.
+        let options = Options {
+            nxdomain_check: false,
+            ..Options::default()
+        };
+        let (_, opened, report) = detect_dns(net(), options);
+        assert!(opened.is_empty());
+        assert!(report
+            .probes
+            .iter()
+            .all(|p| !p.endpoint.starts_with("reauthfi-")));
+    }
+
+    #[test]
+    fn clean_dns_answers_add_no_evidence() {
+        let resolver = StubResolver::new().answer("probe.test", &[ip("17.253.144.10")]);
+        let net = FakeNetworkClient::new()
+            .respond(DNS_PROBE, ProbeResponse::new(204, DNS_PROBE))
+            .with_resolver(resolver);
+
+        let (status, opened, report) = detect_dns(net, Options::default());
+
+        assert_eq!(status, ExecutionStatus::Completed);
+        assert!(opened.is_empty());
+        let verdict = report.verdict.unwrap();
+        assert!(!verdict.captive);
+        assert!(verdict
+            .evidence
+            .iter()
+            .all(|e| e.stage == ProbeStage::Standard));
+
+        // Lookups failing do not make the network look broken either.
+        let net = FakeNetworkClient::new()
+            .respond(DNS_PROBE, ProbeResponse::new(204, DNS_PROBE))
+            .with_resolver(StubResolver::new());
+        let (status, _, report) = detect_dns(net, Options::default());
+        assert_eq!(status, ExecutionStatus::Completed);
+        assert!(report.errors.is_empty());
+    }
+
+    #[test]
+    fn dns_checks_can_be_turned_off() {
+        let options = Options {
+            dns_check: false,
+            ..Options::default()
+        };
+        let net = FakeNetworkClient::new()
+            .respond(DNS_PROBE, ProbeResponse::new(204, DNS_PROBE))
+            .with_resolver(StubResolver::new().otherwise(&[ip("10.0.0.1")]));
+
+        let (_, opened, report) = detect_dns(net, options);
+
+        assert!(opened.is_empty());
+        assert!(report.probes.iter().all(|p| p.stage != ProbeStage::Dns));
+    }
+
+    #[test]
+    fn dual_stack_networks_are_probed_per_family() {
+        let config = DetectionConfig {
+            detection_endpoints: Cow::Borrowed(PROBE_ENDPOINTS),
+            gateway_endpoints: Cow::Owned(vec![Cow::Borrowed("/")]),
+            ..dummy_config()
+        };
+        let runner = ScriptedRunner::new(&[
+            ("route -n get -inet6 default", Ok("gateway: fe80::1%en0")),
+            ("route", Ok("gateway: 10.0.0.1")),
+        ]);
+        let options = Options {
+            verdict: VerdictStrategy::Majority,
+            ..Options::default()
+        };
+        let reporter = JsonReporter::default();
+        let url = "http://probe.test/generate_204";
+        let net = FakeNetworkClient::new()
+            .respond_over(AddressFamily::Ipv4, url, ProbeResponse::new(204, url))
+            .respond_over(
+                AddressFamily::Ipv6,
+                url,
+                ProbeResponse::new(302, url).header("location", "http://portal.test/login"),
+            )
+            .otherwise(404);
+        let ctx = fake_context(&config, net, &runner, &options, &reporter);
+        let opener = RecordingOpener::default();
+
+        detect_portal(&ctx, &opener);
+
+        assert_eq!(opener.opened(), vec!["http://portal.test/login"]);
+        let report = reporter.into_report();
+        let family = |family, gateway: &str, portal| FamilyResult {
+            family,
+            gateway: Some(gateway.to_string()),
+            portal,
+            reachable: true,
+        };
+        assert_eq!(
+            report.families,
+            [
+                family(AddressFamily::Ipv4, "10.0.0.1", false),
+                family(AddressFamily::Ipv6, "fe80::1%en0", true),
+            ]
+        );
+        let mut probes: Vec<(&str, &str)> = report
+            .probes
+            .iter()
+            .map(|p| (p.endpoint.as_str(), p.url.as_str()))
+            .collect();
+        probes.sort();
+        assert_eq!(
+            probes,
+            [
+                ("Gateway/ (IPv4)", "http://10.0.0.1/"),
+                ("Gateway/ (IPv6)", "http://[fe80::1%25en0]/"),
+                ("Probe (IPv4)", url),
+                ("Probe (IPv6)", url),
+            ]
+        );
+
+        // Any-portal stops at the IPv6 gateway's portal, which answers last.
+        let options = Options::default();
+        let reporter = JsonReporter::default();
+        let gateway = "http://[fe80::1%25en0]/";
+        let net = FakeNetworkClient::new()
+            .respond(url, ProbeResponse::new(204, url))
+            .redirect(gateway, 302, "http://portal.test/login")
+            .delay(gateway, Duration::from_millis(200))
+            .otherwise(404);
+        let ctx = fake_context(&config, net, &runner, &options, &reporter);
+        let opener = RecordingOpener::default();
+
+        detect_portal(&ctx, &opener);
+
+        assert_eq!(opener.opened(), vec!["http://portal.test/login"]);
+        assert_eq!(
+            reporter.into_report().families,
+            [
+                family(AddressFamily::Ipv4, "10.0.0.1", false),
+                family(AddressFamily::Ipv6, "fe80::1%en0", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn ipv6_only_networks_probe_the_ipv6_gateway() {
+        let config = DetectionConfig {
+            gateway_endpoints: Cow::Owned(vec![Cow::Borrowed("/")]),
+            ..dummy_config()
+        };
+        let runner =
+            ScriptedRunner::new(&[("route -n get -inet6 default", Ok("gateway: 2001:db8::1"))]);
+        let options = Options::default();
+        let net = FakeNetworkClient::new().redirect(
+            "http://[2001:db8::1]/",
+            302,
+            "http://portal.test/login",
+        );
+        let ctx = fake_context(&config, net, &runner, &options, &SilentReporter);
+
+        assert_eq!(
+            detect_gateway(&ctx),
+            DetectionResult::PortalFound("http://portal.test/login".to_string())
+        );
+    }
+
+    #[test]
+    fn classify_response_reads_a_streamed_body() {
+        let body = std::io::Cursor::new(r#"<a href="/login?next=/">Sign in</a>"#);
+        let response = ProbeResponse::new(200, "http://probe.test/generate_204").body_reader(body);
+        let mut target = base_target();
+        target.expected_status = Some(204);
+
+        let outcome = classify_response(&target, response);
+        assert!(
+            matches!(outcome, Outcome::Portal(url) if url == "http://example.com/login?next=/")
+        );
+    }
+
+    #[test]
+    fn builder_customizes_a_preset_without_touching_it() {
+        let config = MACOS_CONFIG
+            .to_builder()
+            .without_endpoint("google")
+            .endpoint(DetectionEndpoint::new(
+                "Corp",
+                format!("http://{}/connectivity", "check.corp.example"),
+                Some(204),
+            ))
+            .gateway_endpoint("/login.html")
+            .gateway_endpoint("/")
+            .build();
+
+        let names: Vec<&str> = config
+            .detection_endpoints
+            .iter()
+            .map(|e| e.name.as_ref())
+            .collect();
+        assert_eq!(names, vec!["Apple", "Corp"]);
+        assert_eq!(config.gateway_endpoints.as_ref(), ["/", "/login.html"]);
+        assert_eq!(MACOS_CONFIG.detection_endpoints.len(), 2);
+    }
+
+    #[test]
+    fn builder_endpoint_replaces_same_name() {
+        let config = MACOS_CONFIG
+            .to_builder()
+            .endpoint(DetectionEndpoint::new(
+                "Apple",
+                "http://apple.test/",
+                Some(200),
+            ))
+            .build();
+
+        assert_eq!(config.detection_endpoints.len(), 2);
+        assert_eq!(config.detection_endpoints[0].url, "http://apple.test/");
+    }
+
+    #[test]
+    fn ssid_is_parsed_from_networksetup_and_nmcli() {
+        assert_eq!(
+            parse_networksetup_ssid("Current Wi-Fi Network: Hotel Guest\n").as_deref(),
+            Some("Hotel Guest")
+        );
+        assert_eq!(
+            parse_networksetup_ssid("You are not associated with an AirPort network.\n"),
+            None
+        );
+        assert_eq!(
+            parse_nmcli_ssid("no:Neighbour\nyes:Cafe\\: Free\n").as_deref(),
+            Some("Cafe: Free")
+        );
+    }
+
+    #[test]
+    fn command_opener_substitutes_url() {
+        let runner = ScriptedRunner::new(&[("firefox", Ok(""))]);
+        let opener = CommandPortalOpener::new("firefox --new-window %s", &runner);
+
+        assert_eq!(opener.open("http://portal").unwrap(), "firefox");
+        assert_eq!(runner.calls(), vec!["firefox --new-window http://portal"]);
     }
 }
@@ -1,54 +1,321 @@
+use std::path::PathBuf;
 use std::process::ExitCode;
+use std::time::Duration;
 
 use colored::Colorize;
-use reauthfi::{run, ExecutionStatus, Options};
+use reauthfi::{
+    captive_api_status, monitor, run_configured, watch, ConfigLayers, ExecutionStatus, Options,
+    Settings, SystemCommandRunner, TerminalReporter, WatchOptions,
+};
 
 const HELP: &str = "\
-reauthfi - macOS Captive Portal auto-detection and opener
+reauthfi - Captive Portal auto-detection and opener for macOS and Linux
 
 Usage:
-  reauthfi [--help] [--version]
+  reauthfi [options]
+  reauthfi watch [options]
+  reauthfi monitor [options]
+  reauthfi status [--json]
+  reauthfi config show [options]
+
+Commands:
+  watch                 Keep checking and open the portal whenever one appears
+  monitor               Detect whenever the default gateway changes (no polling)
+  status                Show the session reported by the network's Captive Portal API
+  config show           Print the settings merged from the config file and flags
 
 Options:
-  -h, --help     Show this help
-  -V, --version  Show version
+      --timeout <secs>  Per-request timeout in seconds (default: 5)
+      --no-open         Detect only; do not open the portal in a browser
+      --print-url       Write only the portal URL to stdout
+      --no-wifi-reset   Never toggle Wi-Fi when the network is not ready
+      --json            Print a single JSON report instead of progress output
+      --follow-redirects <n>
+                        Follow up to n portal redirects to the landing page (default: 0)
+      --verdict <strategy>
+                        How probe answers are weighed: any-portal (default), majority
+                        or all-must-pass
+      --no-dns-check    Do not look for portals that hijack DNS answers
+      --no-nxdomain-check
+                        Do not treat answers for made-up names as DNS hijacks
+  -v, --verbose         Also print redirect chains and the evidence behind the verdict
+      --gateway <addr>  Probe this host[:port] instead of the default gateway
+      --config <path>   Config file (default: ~/.config/reauthfi/config.toml)
+  -h, --help            Show this help
+  -V, --version         Show version
+
+Watch options:
+      --interval <secs>      Delay between checks (default: 30)
+      --max-interval <secs>  Backoff limit while captive or offline (default: 300)
+      --cooldown <secs>      Never reopen the same portal sooner (default: 600)
+      --expiry-margin <secs> Open the portal this long before a reported session
+                             ends (default: 60)
 ";
 
-fn main() -> ExitCode {
-    let mut args = std::env::args().skip(1);
-    let first = args.next();
-    if args.next().is_some() {
-        eprintln!("Too many arguments");
-        eprintln!();
-        eprintln!("{HELP}");
-        return ExitCode::FAILURE;
-    }
-    if let Some(arg) = first {
-        match arg.as_str() {
-            "-h" | "--help" => {
-                println!("{HELP}");
-                return ExitCode::SUCCESS;
+/// Flags that only make sense for `watch`.
+const WATCH_FLAGS: &[&str] = &[
+    "--interval",
+    "--max-interval",
+    "--cooldown",
+    "--expiry-margin",
+];
+
+enum Command {
+    Detect,
+    Watch,
+    Monitor,
+    Status,
+    ConfigShow,
+}
+
+struct Invocation {
+    command: Command,
+    options: Options,
+    /// Flags that override the config file.
+    overrides: Settings,
+    config_path: Option<PathBuf>,
+    watch: WatchOptions,
+}
+
+enum Cli {
+    Run(Box<Invocation>),
+    Help,
+    Version,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Cli, String> {
+    let mut options = Options::default();
+    let mut overrides = Settings::default();
+    let mut config_path = None;
+    let mut watch = WatchOptions::default();
+    let mut watch_flag: Option<String> = None;
+    let mut positional: Vec<String> = Vec::new();
+
+    while let Some(arg) = args.next() {
+        let (flag, mut inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
             }
-            "-V" | "--version" => {
-                println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
-                return ExitCode::SUCCESS;
+            _ => (arg.clone(), None),
+        };
+        if watch_flag.is_none() && WATCH_FLAGS.contains(&flag.as_str()) {
+            watch_flag = Some(flag.clone());
+        }
+        let mut value = |name: &str| match inline_value.take() {
+            Some(value) => Ok(value),
+            None => args
+                .next()
+                .ok_or_else(|| format!("{name} requires a value")),
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Cli::Help),
+            "-V" | "--version" => return Ok(Cli::Version),
+            "--timeout" => {
+                let value = value("--timeout")?;
+                overrides.timeout = match value.parse::<u64>() {
+                    Ok(secs) if secs > 0 => Some(secs),
+                    _ => return Err(format!("Invalid --timeout value: {value}")),
+                };
             }
-            _ => {
-                eprintln!("Unknown argument: {arg}");
-                eprintln!();
-                eprintln!("{HELP}");
-                return ExitCode::FAILURE;
+            "--no-open" => options.open = false,
+            "--print-url" => options.print_url = true,
+            "--no-wifi-reset" => overrides.wifi_reset = Some(false),
+            "--no-dns-check" => overrides.dns_check = Some(false),
+            "--no-nxdomain-check" => overrides.nxdomain_check = Some(false),
+            "--json" => options.json = true,
+            "-v" | "--verbose" => options.verbose = true,
+            "--follow-redirects" => {
+                let value = value("--follow-redirects")?;
+                overrides.follow_redirects = match value.parse::<u32>() {
+                    Ok(limit) => Some(limit),
+                    _ => return Err(format!("Invalid --follow-redirects value: {value}")),
+                };
             }
+            "--verdict" => {
+                let value = value("--verdict")?;
+                overrides.verdict = Some(value.parse().map_err(|_| {
+                    format!(
+                        "Invalid --verdict value: {value} (any-portal, majority, all-must-pass)"
+                    )
+                })?);
+            }
+            "--gateway" => overrides.gateway = Some(value("--gateway")?),
+            "--config" => config_path = Some(PathBuf::from(value("--config")?)),
+            "--interval" => watch.interval = seconds("--interval", value("--interval")?)?,
+            "--max-interval" => {
+                watch.max_interval = seconds("--max-interval", value("--max-interval")?)?
+            }
+            "--cooldown" => watch.cooldown = seconds("--cooldown", value("--cooldown")?)?,
+            "--expiry-margin" => {
+                let margin = seconds("--expiry-margin", value("--expiry-margin")?)?;
+                overrides.expiry_margin = Some(margin.as_secs());
+            }
+            _ if !arg.starts_with('-') => {
+                positional.push(arg);
+                continue;
+            }
+            _ => return Err(format!("Unknown argument: {arg}")),
         }
+        // `--flag=value` for a flag that takes no value.
+        if inline_value.is_some() {
+            return Err(format!("Unknown argument: {arg}"));
+        }
+    }
+
+    if options.json && options.print_url {
+        return Err("--json and --print-url cannot be combined".to_string());
     }
-    let options = Options::default();
 
-    match run(&options) {
+    let command = match positional.as_slice() {
+        [] => Command::Detect,
+        [cmd] if cmd == "watch" => Command::Watch,
+        [cmd] if cmd == "monitor" => Command::Monitor,
+        [cmd] if cmd == "status" => Command::Status,
+        [config, show] if config == "config" && show == "show" => Command::ConfigShow,
+        _ => return Err(format!("Unknown command: {}", positional.join(" "))),
+    };
+    if options.json {
+        let unsupported = match command {
+            Command::Watch => Some("watch"),
+            Command::Monitor => Some("monitor"),
+            Command::ConfigShow => Some("config show"),
+            Command::Detect | Command::Status => None,
+        };
+        if let Some(name) = unsupported {
+            return Err(format!("--json cannot be used with {name}"));
+        }
+    }
+    if let Some(flag) = watch_flag.filter(|_| !matches!(command, Command::Watch)) {
+        return Err(format!("{flag} can only be used with watch"));
+    }
+    watch.max_interval = watch.max_interval.max(watch.interval);
+
+    Ok(Cli::Run(Box::new(Invocation {
+        command,
+        options,
+        overrides,
+        config_path,
+        watch,
+    })))
+}
+
+fn seconds(flag: &str, value: String) -> Result<Duration, String> {
+    match value.parse::<u64>() {
+        Ok(secs) if secs > 0 => Ok(Duration::from_secs(secs)),
+        _ => Err(format!("Invalid {flag} value: {value}")),
+    }
+}
+
+fn main() -> ExitCode {
+    let invocation = match parse_args(std::env::args().skip(1)) {
+        Ok(Cli::Run(invocation)) => *invocation,
+        Ok(Cli::Help) => {
+            println!("{HELP}");
+            return ExitCode::SUCCESS;
+        }
+        Ok(Cli::Version) => {
+            println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+            return ExitCode::SUCCESS;
+        }
+        Err(msg) => {
+            eprintln!("{msg}");
+            eprintln!();
+            eprintln!("{HELP}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let json = invocation.options.json;
+
+    let result = ConfigLayers::load(
+        invocation.config_path,
+        &invocation.overrides,
+        invocation.options,
+    )
+    .and_then(|layers| {
+        let current = || layers.resolve_current(&SystemCommandRunner);
+        let reporter =
+            || TerminalReporter::new(layers.options.print_url).verbose(layers.options.verbose);
+        match invocation.command {
+            Command::Detect => {
+                let effective = current();
+                run_configured(&effective.config, &effective.options)
+            }
+            // Both pick the network's profiles again on every check.
+            Command::Watch => {
+                watch(&layers, invocation.watch, &reporter()).map(|()| ExecutionStatus::Completed)
+            }
+            Command::Monitor => monitor(&layers, &reporter()).map(|()| ExecutionStatus::Completed),
+            Command::Status => {
+                let effective = current();
+                captive_api_status(&effective.config, &effective.options).map(|status| {
+                    if json {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&status).unwrap_or_default()
+                        );
+                    } else {
+                        print!("{status}");
+                    }
+                    ExecutionStatus::Completed
+                })
+            }
+            Command::ConfigShow => {
+                print!("{}", current().to_toml());
+                Ok(ExecutionStatus::Completed)
+            }
+        }
+    });
+
+    match result {
         Ok(ExecutionStatus::Completed) => ExitCode::SUCCESS,
         Ok(ExecutionStatus::NetworkNotReady) => ExitCode::from(2),
+        Err(err) if json => {
+            let document = serde_json::json!({ "status": "error", "errors": [err.to_string()] });
+            println!("{document}");
+            ExitCode::FAILURE
+        }
         Err(err) => {
             eprintln!("{} {}", "❌".red().bold(), err);
             ExitCode::FAILURE
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(args: &[&str]) -> String {
+        match parse_args(args.iter().map(|arg| arg.to_string())) {
+            Err(msg) => msg,
+            Ok(_) => panic!("{args:?} should be rejected"),
+        }
+    }
+
+    #[test]
+    fn json_is_rejected_for_config_show() {
+        assert_eq!(
+            parse_error(&["config", "show", "--json"]),
+            "--json cannot be used with config show"
+        );
+    }
+
+    #[test]
+    fn watch_flags_are_rejected_outside_watch() {
+        for flag in WATCH_FLAGS {
+            assert_eq!(
+                parse_error(&[flag, "10"]),
+                format!("{flag} can only be used with watch")
+            );
+            assert_eq!(
+                parse_error(&["monitor", &format!("{flag}=10")]),
+                format!("{flag} can only be used with watch")
+            );
+        }
+        assert!(matches!(
+            parse_args(["watch", "--interval", "10"].map(String::from).into_iter()),
+            Ok(Cli::Run(_))
+        ));
+    }
+}
@@ -0,0 +1,397 @@
+use std::io::{BufRead, BufReader, Lines};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{
+    detect_portal, get_gateway_ip, portal_opener, Clock, CommandRunner, ConfigLayers,
+    DetectionContext, ExecutionStatus, HttpClient, NetworkClient, PortalOpener, ReauthfiError,
+    ReportEvent, Reporter, SystemClock, SystemCommandRunner,
+};
+
+/// DHCP replaces addresses and routes in several messages; wait for them to settle.
+const SETTLE_DELAY: Duration = Duration::from_secs(2);
+
+/// A source of routing table and interface address changes.
+pub trait RouteMonitor {
+    /// Blocks until the next change. Returns `Ok(false)` once the source has ended.
+    fn wait_for_change(&mut self) -> Result<bool, ReauthfiError>;
+}
+
+/// Listens on an rtnetlink socket for IPv4/IPv6 route and address changes.
+#[cfg(target_os = "linux")]
+pub struct NetlinkMonitor {
+    socket: std::os::fd::OwnedFd,
+}
+
+#[cfg(target_os = "linux")]
+impl NetlinkMonitor {
+    pub fn new() -> Result<Self, ReauthfiError> {
+        use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+        // SAFETY: plain socket(2) call; the descriptor is owned right after the check.
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                libc::NETLINK_ROUTE,
+            )
+        };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        // SAFETY: `fd` is a freshly created, valid descriptor nobody else owns.
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        // SAFETY: sockaddr_nl is plain old data; all-zero is a valid value.
+        let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_groups = (libc::RTMGRP_IPV4_ROUTE
+            | libc::RTMGRP_IPV6_ROUTE
+            | libc::RTMGRP_IPV4_IFADDR
+            | libc::RTMGRP_IPV6_IFADDR) as u32;
+        // SAFETY: `addr` outlives the call and the length matches its type.
+        let rc = unsafe {
+            libc::bind(
+                socket.as_raw_fd(),
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if rc < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(Self { socket })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl RouteMonitor for NetlinkMonitor {
+    fn wait_for_change(&mut self) -> Result<bool, ReauthfiError> {
+        use std::os::fd::AsRawFd;
+
+        // The content does not matter: any message means the gateway should be re-read.
+        let mut buf = [0u8; 8192];
+        loop {
+            // SAFETY: `buf` is valid for writes of its full length.
+            let n = unsafe {
+                libc::recv(
+                    self.socket.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                    0,
+                )
+            };
+            if n >= 0 {
+                return Ok(n > 0);
+            }
+            let err = std::io::Error::last_os_error();
+            match err.raw_os_error() {
+                Some(libc::EINTR) => continue,
+                // The kernel dropped messages because we were slow; that is a change too.
+                Some(libc::ENOBUFS) => return Ok(true),
+                _ => return Err(err.into()),
+            }
+        }
+    }
+}
+
+/// Reads the output of `route -n monitor`, which starts every routing message
+/// with a `got message of size ...` line.
+pub struct RouteCommandMonitor {
+    child: Child,
+    lines: Lines<BufReader<ChildStdout>>,
+}
+
+impl RouteCommandMonitor {
+    pub fn new() -> Result<Self, ReauthfiError> {
+        let mut child = Command::new("route")
+            .args(["-n", "monitor"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| ReauthfiError::CommandFailed("route monitor has no output".into()))?;
+        Ok(Self {
+            child,
+            lines: BufReader::new(stdout).lines(),
+        })
+    }
+}
+
+impl RouteMonitor for RouteCommandMonitor {
+    fn wait_for_change(&mut self) -> Result<bool, ReauthfiError> {
+        for line in self.lines.by_ref() {
+            if is_route_message(&line?) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl Drop for RouteCommandMonitor {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn is_route_message(line: &str) -> bool {
+    line.starts_with("got message")
+}
+
+/// The route monitor for the current platform.
+pub fn route_monitor() -> Result<Box<dyn RouteMonitor>, ReauthfiError> {
+    #[cfg(target_os = "linux")]
+    {
+        Ok(Box::new(NetlinkMonitor::new()?))
+    }
+    #[cfg(target_os = "macos")]
+    {
+        Ok(Box::new(RouteCommandMonitor::new()?))
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    {
+        Err(ReauthfiError::UnsupportedPlatform)
+    }
+}
+
+/// Runs detection whenever the default gateway changes, with the profiles of the
+/// network it changed to.
+pub struct GatewayTrigger<'a> {
+    layers: &'a ConfigLayers,
+    net: Arc<dyn NetworkClient>,
+    commands: &'a dyn CommandRunner,
+    /// Used instead of the launcher the resolved options name.
+    opener: Option<&'a dyn PortalOpener>,
+    reporter: &'a dyn Reporter,
+    clock: &'a dyn Clock,
+    gateway: Option<String>,
+}
+
+impl<'a> GatewayTrigger<'a> {
+    pub fn new(
+        layers: &'a ConfigLayers,
+        net: Arc<dyn NetworkClient>,
+        commands: &'a dyn CommandRunner,
+        reporter: &'a dyn Reporter,
+        clock: &'a dyn Clock,
+    ) -> Self {
+        Self {
+            layers,
+            net,
+            commands,
+            opener: None,
+            reporter,
+            clock,
+            gateway: None,
+        }
+    }
+
+    /// Opens portals with `opener` whatever the network's profile says.
+    pub fn opener(mut self, opener: &'a dyn PortalOpener) -> Self {
+        self.opener = Some(opener);
+        self
+    }
+
+    pub fn gateway(&self) -> Option<&str> {
+        self.gateway.as_deref()
+    }
+
+    /// Re-reads the gateway and runs detection if it changed to a new one.
+    /// Returns whether detection ran.
+    pub fn check(&mut self) -> bool {
+        let effective = self.layers.resolve_current(self.commands);
+        let gateway = get_gateway_ip(&effective.config, self.commands).ok();
+        if gateway == self.gateway {
+            return false;
+        }
+        self.gateway = gateway;
+        self.reporter.report(&ReportEvent::GatewayChanged {
+            gateway: self.gateway.as_deref(),
+        });
+        if self.gateway.is_none() {
+            return false;
+        }
+
+        let ctx = DetectionContext {
+            config: &effective.config,
+            net: self.net.clone(),
+            commands: self.commands,
+            options: &effective.options,
+            reporter: self.reporter,
+        };
+        let configured;
+        let opener = match self.opener {
+            Some(opener) => opener,
+            None => {
+                configured = portal_opener(&effective.options, self.commands);
+                configured.as_ref()
+            }
+        };
+        self.reporter.report(&ReportEvent::DetectionStarted);
+        let (status, errors) = detect_portal(&ctx, opener);
+        if status == ExecutionStatus::NetworkNotReady {
+            self.reporter
+                .report(&ReportEvent::NetworkNotReady { errors: &errors });
+        }
+        true
+    }
+
+    /// Checks once for the current network, then after every change `monitor` reports.
+    pub fn run(&mut self, monitor: &mut dyn RouteMonitor) -> Result<(), ReauthfiError> {
+        self.check();
+        while monitor.wait_for_change()? {
+            self.clock.sleep(SETTLE_DELAY);
+            self.check();
+        }
+        Ok(())
+    }
+}
+
+/// Waits for network changes and runs detection each time the gateway changes,
+/// until the process is stopped.
+pub fn monitor(layers: &ConfigLayers, reporter: &dyn Reporter) -> Result<(), ReauthfiError> {
+    let net = Arc::new(HttpClient::new(layers.options.timeout)?);
+    let commands = SystemCommandRunner;
+    let mut routes = route_monitor()?;
+
+    reporter.report(&ReportEvent::MonitorStarted);
+    GatewayTrigger::new(layers, net, &commands, reporter, &SystemClock).run(routes.as_mut())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::testing::{layers, portal_script, probe_config, FakeClock, RecordingOpener};
+    use crate::{ConfigFile, FakeNetworkClient, ProbeResponse, SilentReporter};
+
+    /// The default gateway as `route -n get default` shows it; `None` means
+    /// there is no default route.
+    #[derive(Clone, Default)]
+    struct RouteTable {
+        gateway: Arc<Mutex<Option<&'static str>>>,
+    }
+
+    impl CommandRunner for RouteTable {
+        fn run(&self, _cmd: &[&str]) -> Result<String, std::io::Error> {
+            match *self.gateway.lock().unwrap() {
+                Some(gateway) => Ok(format!("   route to: default\n    gateway: {gateway}\n")),
+                None => Err(std::io::Error::other("not in table")),
+            }
+        }
+    }
+
+    /// Moves `table` to each scripted gateway in turn, reporting a change each time.
+    struct FakeRouteMonitor {
+        table: RouteTable,
+        changes: VecDeque<Option<&'static str>>,
+    }
+
+    impl RouteMonitor for FakeRouteMonitor {
+        fn wait_for_change(&mut self) -> Result<bool, ReauthfiError> {
+            let Some(gateway) = self.changes.pop_front() else {
+                return Ok(false);
+            };
+            *self.table.gateway.lock().unwrap() = gateway;
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn detection_runs_only_when_gateway_changes() {
+        let layers = layers(probe_config());
+        let opener = RecordingOpener::default();
+        let clock = FakeClock::new();
+        let commands = RouteTable::default();
+        *commands.gateway.lock().unwrap() = Some("10.0.0.1");
+        let mut routes = FakeRouteMonitor {
+            table: commands.clone(),
+            changes: [
+                Some("10.0.0.1"),
+                Some("192.168.1.1"),
+                None,
+                Some("192.168.1.1"),
+            ]
+            .into(),
+        };
+        let net = Arc::new(portal_script(&[
+            Some("http://cafe.test/login"),
+            Some("http://hotel.test/login"),
+            Some("http://hotel.test/login?again"),
+        ]));
+        let mut trigger =
+            GatewayTrigger::new(&layers, net, &commands, &SilentReporter, &clock).opener(&opener);
+
+        trigger.run(&mut routes).unwrap();
+
+        assert_eq!(
+            opener.opened(),
+            vec![
+                "http://cafe.test/login",
+                "http://hotel.test/login",
+                "http://hotel.test/login?again",
+            ]
+        );
+        assert_eq!(trigger.gateway(), Some("192.168.1.1"));
+        assert_eq!(clock.slept(), vec![SETTLE_DELAY; 4]);
+    }
+
+    #[test]
+    fn each_gateway_gets_the_profile_for_it() {
+        let mut layers = layers(probe_config());
+        layers.file = ConfigFile::parse(
+            r#"
+[networks."192.168.1.1"]
+disable_endpoints = ["Probe"]
+
+[[networks."192.168.1.1".endpoints]]
+name = "Hotel"
+url = "http://hotel.test/check"
+expected_status = 204
+"#,
+        )
+        .unwrap();
+        let opener = RecordingOpener::default();
+        let clock = FakeClock::new();
+        let commands = RouteTable::default();
+        *commands.gateway.lock().unwrap() = Some("10.0.0.1");
+        let mut routes = FakeRouteMonitor {
+            table: commands.clone(),
+            changes: [Some("192.168.1.1")].into(),
+        };
+        let probe = "http://probe.test/generate_204";
+        let net = Arc::new(
+            FakeNetworkClient::new()
+                .respond(probe, ProbeResponse::new(204, probe))
+                .redirect("http://hotel.test/check", 302, "/login"),
+        );
+        let mut trigger =
+            GatewayTrigger::new(&layers, net.clone(), &commands, &SilentReporter, &clock)
+                .opener(&opener);
+
+        trigger.run(&mut routes).unwrap();
+
+        assert_eq!(opener.opened(), vec!["http://hotel.test/login"]);
+        assert_eq!(
+            net.requests(),
+            vec![probe.to_string(), "http://hotel.test/check".to_string()]
+        );
+    }
+
+    #[test]
+    fn route_monitor_output_is_split_into_messages() {
+        let output = "got message of size 192 on Mon Oct 12 10:00:00 2026\n\
+                      RTM_ADD: Add Route: len 192, pid: 0, seq 0, errno 0, flags:<UP,GATEWAY,DONE>\n\
+                      locks:  inits:\n";
+        let messages = output.lines().filter(|l| is_route_message(l)).count();
+        assert_eq!(messages, 1);
+    }
+}
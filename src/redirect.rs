@@ -0,0 +1,164 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::{extract_redirect_url, resolve_url, NetworkClient, ProbeResponse};
+
+/// One URL on the way from a probe to the portal's landing page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RedirectHop {
+    pub url: String,
+    /// `None` when the URL was not fetched: HTTPS, the hop limit, a loop or an error.
+    pub status_code: Option<u16>,
+}
+
+/// Fetches `url` and every redirect after it, up to `limit` requests, and returns
+/// the hops in order; the last one is the landing page.
+///
+/// Only plain HTTP is fetched: portals rarely have valid certificates for the
+/// name they intercept, so an HTTPS hop ends the chain without being requested.
+pub fn follow_redirects(
+    net: &dyn NetworkClient,
+    url: &str,
+    limit: u32,
+    timeout: Duration,
+) -> Vec<RedirectHop> {
+    follow_redirects_until(net, url, limit, timeout, &AtomicBool::new(false))
+}
+
+/// Like [`follow_redirects`], but stops before the next request once `cancel` is set.
+pub(crate) fn follow_redirects_until(
+    net: &dyn NetworkClient,
+    url: &str,
+    limit: u32,
+    timeout: Duration,
+    cancel: &AtomicBool,
+) -> Vec<RedirectHop> {
+    let mut hops: Vec<RedirectHop> = Vec::new();
+    let mut url = url.to_string();
+
+    for _ in 0..limit {
+        let seen = hops.iter().any(|hop| hop.url == url);
+        if seen || !url.starts_with("http://") || cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        let Ok(response) = net.get(&url, timeout) else {
+            break;
+        };
+        let status_code = response.status();
+        let next = next_hop(&url, response);
+        hops.push(RedirectHop {
+            url,
+            status_code: Some(status_code),
+        });
+        match next {
+            Some(next) => url = next,
+            None => return hops,
+        }
+    }
+
+    hops.push(RedirectHop {
+        url,
+        status_code: None,
+    });
+    hops
+}
+
+/// Where the page at `url` sends the browser: its `Location`, or a meta refresh
+/// or JavaScript redirect in a successful page.
+fn next_hop(url: &str, response: ProbeResponse) -> Option<String> {
+    if response.is_redirection() {
+        return resolve_url(url, response.header_value("location")?);
+    }
+    if !response.is_success() {
+        return None;
+    }
+    extract_redirect_url(&response.text().ok()?, url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FakeNetworkClient;
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    fn urls(hops: &[RedirectHop]) -> Vec<(&str, Option<u16>)> {
+        hops.iter()
+            .map(|hop| (hop.url.as_str(), hop.status_code))
+            .collect()
+    }
+
+    #[test]
+    fn chain_is_followed_to_the_landing_page() {
+        let net = FakeNetworkClient::new()
+            .redirect("http://1.1.1.1/redirect?x=1", 302, "/portal")
+            .page(
+                "http://1.1.1.1/portal",
+                200,
+                r#"<meta http-equiv="refresh" content="0; url=http://10.0.0.1/login">"#,
+            )
+            .page("http://10.0.0.1/login", 200, "<form action='/auth'></form>");
+
+        let hops = follow_redirects(&net, "http://1.1.1.1/redirect?x=1", 5, TIMEOUT);
+        assert_eq!(
+            urls(&hops),
+            [
+                ("http://1.1.1.1/redirect?x=1", Some(302)),
+                ("http://1.1.1.1/portal", Some(200)),
+                ("http://10.0.0.1/login", Some(200)),
+            ]
+        );
+    }
+
+    #[test]
+    fn https_ends_the_chain_unfetched() {
+        let net =
+            FakeNetworkClient::new().redirect("http://gw.test/", 302, "https://portal.test/login");
+
+        let hops = follow_redirects(&net, "http://gw.test/", 5, TIMEOUT);
+        assert_eq!(
+            urls(&hops),
+            [
+                ("http://gw.test/", Some(302)),
+                ("https://portal.test/login", None)
+            ]
+        );
+        assert_eq!(net.requests(), ["http://gw.test/"]);
+    }
+
+    #[test]
+    fn limit_and_loops_stop_the_chain() {
+        let net = FakeNetworkClient::new()
+            .redirect("http://a.test/", 302, "http://b.test/")
+            .redirect("http://b.test/", 302, "http://a.test/");
+
+        let hops = follow_redirects(&net, "http://a.test/", 1, TIMEOUT);
+        assert_eq!(
+            urls(&hops),
+            [("http://a.test/", Some(302)), ("http://b.test/", None)]
+        );
+
+        let hops = follow_redirects(&net, "http://a.test/", 10, TIMEOUT);
+        assert_eq!(
+            urls(&hops),
+            [
+                ("http://a.test/", Some(302)),
+                ("http://b.test/", Some(302)),
+                ("http://a.test/", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn cancelled_chains_stop_before_the_next_request() {
+        let net = FakeNetworkClient::new().redirect("http://a.test/", 302, "http://b.test/");
+
+        let hops =
+            follow_redirects_until(&net, "http://a.test/", 5, TIMEOUT, &AtomicBool::new(true));
+
+        assert_eq!(urls(&hops), [("http://a.test/", None)]);
+        assert!(net.requests().is_empty());
+    }
+}
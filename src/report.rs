@@ -0,0 +1,780 @@
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use colored::Colorize;
+use serde::Serialize;
+
+use crate::{AddressFamily, ExecutionStatus, RedirectHop, Verdict, WatchState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeStage {
+    CaptiveApi,
+    Standard,
+    Gateway,
+    /// Lookups of the probe hosts and a made-up name.
+    Dns,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeOutcome {
+    Portal,
+    /// HTTP 511 Network Authentication Required; also a portal.
+    AuthenticationRequired,
+    Ok,
+    Mismatch,
+    /// The expected status with a different page than the endpoint serves.
+    BodyMismatch,
+    Error,
+}
+
+/// Progress of a run, in the order it happens.
+#[derive(Debug, Clone)]
+pub enum ReportEvent<'a> {
+    DetectionStarted,
+    AttemptStarted {
+        attempt: u32,
+    },
+    /// A default router was found; before any probe is sent.
+    GatewayFound {
+        family: AddressFamily,
+        address: &'a str,
+    },
+    StageStarted {
+        stage: ProbeStage,
+        endpoints: usize,
+    },
+    /// `family` is set when the probe is tied to one address family.
+    ProbeStarted {
+        stage: ProbeStage,
+        family: Option<AddressFamily>,
+        endpoint: &'a str,
+        url: &'a str,
+    },
+    ProbeOutcome {
+        stage: ProbeStage,
+        family: Option<AddressFamily>,
+        endpoint: &'a str,
+        url: &'a str,
+        outcome: ProbeOutcome,
+        status_code: Option<u16>,
+        elapsed: Duration,
+        /// Portal URL for `Portal`, error message for `Error`.
+        detail: Option<&'a str>,
+    },
+    /// Follows the `ProbeOutcome` of a portal when redirects are followed;
+    /// starts with the probe and ends with the landing page.
+    RedirectChain {
+        stage: ProbeStage,
+        endpoint: &'a str,
+        hops: &'a [RedirectHop],
+    },
+    /// The probes' answers weighed together; before `PortalFound` when captive.
+    Verdict {
+        verdict: &'a Verdict,
+    },
+    PortalFound {
+        url: &'a str,
+    },
+    OpeningPortal {
+        url: &'a str,
+    },
+    PortalOpened {
+        launcher: &'a str,
+    },
+    NoPortalDetected,
+    /// Reported by the network's Captive Portal API.
+    SessionRemaining {
+        seconds: Option<u64>,
+        bytes: Option<u64>,
+    },
+    AttemptFinished {
+        status: ExecutionStatus,
+        errors: &'a [String],
+    },
+    WifiResetStarted {
+        device: &'a str,
+    },
+    Waiting {
+        duration: Duration,
+    },
+    NetworkNotReady {
+        errors: &'a [String],
+    },
+    WatchStarted {
+        interval: Duration,
+    },
+    WatchStateChanged {
+        state: WatchState,
+        portal_url: Option<&'a str>,
+    },
+    MonitorStarted,
+    /// `None` when the default route went away.
+    GatewayChanged {
+        gateway: Option<&'a str>,
+    },
+    /// The portal was opened within the watch cooldown.
+    PortalOpenSkipped {
+        url: &'a str,
+    },
+    PortalOpenFailed {
+        error: &'a str,
+    },
+}
+
+pub trait Reporter: Send + Sync {
+    fn report(&self, event: &ReportEvent);
+}
+
+/// Discards every event; for embedding detection in other programs.
+pub struct SilentReporter;
+
+impl Reporter for SilentReporter {
+    fn report(&self, _event: &ReportEvent) {}
+}
+
+/// The colored, emoji-decorated terminal output of the CLI.
+pub struct TerminalReporter {
+    print_url: bool,
+    verbose: bool,
+    stdout: Mutex<Box<dyn Write + Send>>,
+    stderr: Mutex<Box<dyn Write + Send>>,
+}
+
+impl Default for TerminalReporter {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+impl TerminalReporter {
+    /// With `print_url`, stdout carries only the portal URL and progress goes to stderr.
+    pub fn new(print_url: bool) -> Self {
+        Self {
+            print_url,
+            verbose: false,
+            stdout: Mutex::new(Box::new(io::stdout())),
+            stderr: Mutex::new(Box::new(io::stderr())),
+        }
+    }
+
+    /// Writes to `stdout` and `stderr` instead of the process's own streams.
+    pub fn output(
+        mut self,
+        stdout: impl Write + Send + 'static,
+        stderr: impl Write + Send + 'static,
+    ) -> Self {
+        self.stdout = Mutex::new(Box::new(stdout));
+        self.stderr = Mutex::new(Box::new(stderr));
+        self
+    }
+
+    /// Also print redirect chains.
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    fn line(&self, line: String) {
+        let stream = if self.print_url {
+            &self.stderr
+        } else {
+            &self.stdout
+        };
+        write_line(stream, &line);
+    }
+}
+
+impl Reporter for TerminalReporter {
+    fn report(&self, event: &ReportEvent) {
+        match event {
+            ReportEvent::DetectionStarted => self.line(format!(
+                "{}",
+                "🔍 Detecting Captive Portal...".cyan().bold()
+            )),
+            ReportEvent::StageStarted {
+                stage: ProbeStage::Standard,
+                endpoints,
+            } => self.line(format!(
+                "  {} Checking captive portal endpoints ({} total)...",
+                "•".yellow(),
+                endpoints
+            )),
+            ReportEvent::StageStarted {
+                stage: ProbeStage::Gateway,
+                ..
+            } => self.line(format!("  {} Checking gateway endpoints...", "•".yellow())),
+            ReportEvent::StageStarted {
+                stage: ProbeStage::Dns,
+                ..
+            } => self.line(format!("  {} Checking DNS answers...", "•".yellow())),
+            ReportEvent::StageStarted {
+                stage: ProbeStage::CaptiveApi,
+                ..
+            } => self.line(format!(
+                "  {} Checking the network's Captive Portal API...",
+                "•".yellow()
+            )),
+            ReportEvent::ProbeOutcome {
+                stage,
+                endpoint,
+                outcome,
+                detail,
+                ..
+            } => match (outcome, stage) {
+                (ProbeOutcome::Portal, ProbeStage::CaptiveApi) => {
+                    self.line(format!("    {} {} reports a portal", "✓".green(), endpoint))
+                }
+                (ProbeOutcome::Portal, ProbeStage::Dns) => self.line(format!(
+                    "    {} {} resolves to a portal",
+                    "✓".green(),
+                    endpoint
+                )),
+                (ProbeOutcome::AuthenticationRequired, _) => self.line(format!(
+                    "    {} {} requires network authentication (511)",
+                    "✓".green(),
+                    endpoint
+                )),
+                (ProbeOutcome::Portal, _) => self.line(format!(
+                    "    {} {} redirect detected",
+                    "✓".green(),
+                    endpoint
+                )),
+                (ProbeOutcome::Error, ProbeStage::Dns) => self.line(format!(
+                    "    {} {} (ignored)",
+                    "⚠️".yellow(),
+                    detail.unwrap_or(endpoint)
+                )),
+                (ProbeOutcome::Error, ProbeStage::Gateway | ProbeStage::CaptiveApi) => self.line(
+                    format!("    {} {} unreachable (ignored)", "⚠️".yellow(), endpoint),
+                ),
+                (ProbeOutcome::Error, ProbeStage::Standard) => {
+                    self.line(format!("    {} {} failed", "✗".red(), endpoint))
+                }
+                (ProbeOutcome::BodyMismatch, _) => self.line(format!(
+                    "    {} {} returned an unexpected page",
+                    "✗".red(),
+                    endpoint
+                )),
+                _ => {}
+            },
+            ReportEvent::GatewayFound { family, address } if self.verbose => self.line(format!(
+                "  {} {} gateway: {}",
+                "•".yellow(),
+                family,
+                address
+            )),
+            ReportEvent::RedirectChain { hops, .. } if self.verbose => {
+                for hop in hops.iter() {
+                    let status = hop
+                        .status_code
+                        .map_or_else(|| "---".to_string(), |code| code.to_string());
+                    self.line(format!("      {} {} {}", "↪".dimmed(), status, hop.url));
+                }
+            }
+            ReportEvent::Verdict { verdict } if self.verbose => {
+                self.line(format!(
+                    "  {} Verdict ({}): {}, {}% confidence",
+                    "•".yellow(),
+                    verdict.strategy,
+                    if verdict.captive { "captive" } else { "open" },
+                    verdict.confidence
+                ));
+                for evidence in &verdict.evidence {
+                    self.line(format!(
+                        "      {} {} from {}",
+                        "·".dimmed(),
+                        evidence.kind,
+                        evidence.endpoint
+                    ));
+                }
+            }
+            ReportEvent::PortalFound { url } => {
+                if self.print_url {
+                    write_line(&self.stdout, url);
+                } else {
+                    self.line(format!("  {} Portal URL: {}", "→".green().bold(), url));
+                }
+            }
+            ReportEvent::OpeningPortal { .. } => {
+                self.line(format!("{}", "📱 Opening in browser...".cyan().bold()))
+            }
+            ReportEvent::PortalOpened { launcher } => {
+                self.line(format!("{} (via {})", "✅ Done!".green().bold(), launcher))
+            }
+            ReportEvent::NoPortalDetected => self.line(format!(
+                "{} No captive portal detected",
+                "✅".green().bold()
+            )),
+            ReportEvent::SessionRemaining { seconds, bytes } => {
+                let remaining: Vec<String> = [
+                    seconds.map(format_seconds),
+                    bytes.map(|b| format!("{} of data", format_bytes(b))),
+                ]
+                .into_iter()
+                .flatten()
+                .collect();
+                self.line(format!(
+                    "  {} Session ends in {}",
+                    "⏱".cyan(),
+                    remaining.join(", ")
+                ))
+            }
+            ReportEvent::WifiResetStarted { device } => self.line(format!(
+                "{} Resetting Wi-Fi on {} and retrying after reconnect...",
+                "↻".yellow(),
+                device
+            )),
+            ReportEvent::Waiting { duration } => self.line(format!(
+                "{} Waiting {}s for Wi-Fi to reconnect...",
+                "⏳".yellow(),
+                duration.as_secs()
+            )),
+            ReportEvent::NetworkNotReady { errors } => {
+                self.line(format!(
+                    "{} Network not ready - this may be a first-time Wi-Fi connection",
+                    "❌".red().bold()
+                ));
+                if cfg!(target_os = "macos") {
+                    self.line("  Close any macOS network popup windows and try again".into());
+                } else {
+                    self.line("  Close any network login popup windows and try again".into());
+                }
+                self.line("  Or wait a few seconds for the network to stabilize".into());
+
+                if !errors.is_empty() {
+                    self.line(format!("  Detail: {}", errors.join(", ")));
+                }
+            }
+            ReportEvent::WatchStarted { interval } => self.line(format!(
+                "{} Watching for captive portals (every {}s, Ctrl-C to stop)...",
+                "👀".cyan().bold(),
+                interval.as_secs()
+            )),
+            ReportEvent::WatchStateChanged { state, .. } => match state {
+                WatchState::Online => self.line(format!("{} Online", "✅".green().bold())),
+                WatchState::Captive => {
+                    self.line(format!("{} Captive portal detected", "🔒".yellow().bold()))
+                }
+                WatchState::Unreachable => {
+                    self.line(format!("{} Network unreachable", "⚠️".yellow()))
+                }
+            },
+            ReportEvent::MonitorStarted => self.line(format!(
+                "{} Waiting for network changes (Ctrl-C to stop)...",
+                "📡".cyan().bold()
+            )),
+            ReportEvent::GatewayChanged {
+                gateway: Some(gateway),
+            } => self.line(format!("{} Gateway is now {}", "↻".yellow(), gateway)),
+            ReportEvent::GatewayChanged { gateway: None } => {
+                self.line(format!("{} Default route removed", "⚠️".yellow()))
+            }
+            ReportEvent::PortalOpenSkipped { .. } => self.line(format!(
+                "  {} Portal was opened recently, not opening again",
+                "•".yellow()
+            )),
+            ReportEvent::PortalOpenFailed { error } => self.line(format!(
+                "{} Failed to open portal: {}",
+                "❌".red().bold(),
+                error
+            )),
+            ReportEvent::AttemptStarted { .. }
+            | ReportEvent::GatewayFound { .. }
+            | ReportEvent::ProbeStarted { .. }
+            | ReportEvent::RedirectChain { .. }
+            | ReportEvent::Verdict { .. }
+            | ReportEvent::AttemptFinished { .. } => {}
+        }
+    }
+}
+
+/// A closed pipe, as with `| head`, must not stop detection.
+fn write_line(stream: &Mutex<Box<dyn Write + Send>>, line: &str) {
+    let _ = writeln!(stream.lock().unwrap(), "{}", line);
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeRecord {
+    pub attempt: u32,
+    pub stage: ProbeStage,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub family: Option<AddressFamily>,
+    pub endpoint: String,
+    pub url: String,
+    pub outcome: ProbeOutcome,
+    pub status_code: Option<u16>,
+    pub elapsed_ms: u64,
+    /// Portal URL for `portal`, error message for `error`.
+    pub detail: Option<String>,
+    /// From the probe to the landing page, with `--follow-redirects`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub redirects: Vec<RedirectHop>,
+}
+
+/// How the probes over one address family fared.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FamilyResult {
+    pub family: AddressFamily,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gateway: Option<String>,
+    /// Some probe over this family led to a portal.
+    pub portal: bool,
+    /// Some probe over this family got an answer.
+    pub reachable: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FoundBy {
+    pub stage: ProbeStage,
+    pub endpoint: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportStatus {
+    PortalFound,
+    #[default]
+    NoPortal,
+    NetworkNotReady,
+    /// A portal was found, at `portal_url`, but the browser could not be opened.
+    OpenFailed,
+}
+
+/// Everything observed during a run; printed as-is by `--json`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DetectionReport {
+    pub status: ReportStatus,
+    pub portal_url: Option<String>,
+    pub found_by: Option<FoundBy>,
+    pub opened_with: Option<String>,
+    pub attempts: u32,
+    pub wifi_reset: bool,
+    /// From the Captive Portal API, when the network provides one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seconds_remaining: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes_remaining: Option<u64>,
+    /// How the probes were weighed, when any of them answered.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verdict: Option<Verdict>,
+    /// Per address family, for the families that have a gateway or their own probes.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub families: Vec<FamilyResult>,
+    pub probes: Vec<ProbeRecord>,
+    pub errors: Vec<String>,
+}
+
+impl DetectionReport {
+    fn finish(&mut self, status: ExecutionStatus, errors: &[String]) {
+        self.status = match status {
+            ExecutionStatus::NetworkNotReady
+                if self.portal_url.is_some() && self.opened_with.is_none() =>
+            {
+                ReportStatus::OpenFailed
+            }
+            ExecutionStatus::NetworkNotReady => ReportStatus::NetworkNotReady,
+            ExecutionStatus::Completed if self.portal_url.is_some() => ReportStatus::PortalFound,
+            ExecutionStatus::Completed => ReportStatus::NoPortal,
+        };
+        self.errors = errors.to_vec();
+    }
+
+    fn family_mut(&mut self, family: AddressFamily) -> &mut FamilyResult {
+        let index = match self.families.iter().position(|f| f.family == family) {
+            Some(index) => index,
+            None => {
+                self.families.push(FamilyResult {
+                    family,
+                    gateway: None,
+                    portal: false,
+                    reachable: false,
+                });
+                self.families.len() - 1
+            }
+        };
+        &mut self.families[index]
+    }
+}
+
+/// Collects events into a [`DetectionReport`] for `--json`.
+#[derive(Default)]
+pub struct JsonReporter {
+    report: Mutex<DetectionReport>,
+}
+
+impl JsonReporter {
+    pub fn into_report(self) -> DetectionReport {
+        self.report.into_inner().unwrap()
+    }
+}
+
+impl Reporter for JsonReporter {
+    fn report(&self, event: &ReportEvent) {
+        let mut report = self.report.lock().unwrap();
+        match event {
+            ReportEvent::AttemptStarted { attempt } => {
+                report.attempts = *attempt;
+                report.portal_url = None;
+                report.found_by = None;
+                report.opened_with = None;
+                report.seconds_remaining = None;
+                report.bytes_remaining = None;
+                report.verdict = None;
+                report.families.clear();
+            }
+            ReportEvent::GatewayFound { family, address } => {
+                report.family_mut(*family).gateway = Some(address.to_string());
+            }
+            ReportEvent::ProbeOutcome {
+                stage,
+                family,
+                endpoint,
+                url,
+                outcome,
+                status_code,
+                elapsed,
+                detail,
+            } => {
+                let is_portal = matches!(
+                    outcome,
+                    ProbeOutcome::Portal | ProbeOutcome::AuthenticationRequired
+                );
+                if is_portal && report.found_by.is_none() {
+                    report.found_by = Some(FoundBy {
+                        stage: *stage,
+                        endpoint: endpoint.to_string(),
+                    });
+                }
+                if let Some(family) = family {
+                    let result = report.family_mut(*family);
+                    result.portal |= is_portal;
+                    result.reachable |= *outcome != ProbeOutcome::Error;
+                }
+                let attempt = report.attempts;
+                report.probes.push(ProbeRecord {
+                    attempt,
+                    stage: *stage,
+                    family: *family,
+                    endpoint: endpoint.to_string(),
+                    url: url.to_string(),
+                    outcome: *outcome,
+                    status_code: *status_code,
+                    elapsed_ms: elapsed.as_millis() as u64,
+                    detail: detail.map(str::to_string),
+                    redirects: Vec::new(),
+                });
+            }
+            ReportEvent::RedirectChain {
+                stage,
+                endpoint,
+                hops,
+            } => {
+                let probe = report
+                    .probes
+                    .iter_mut()
+                    .rev()
+                    .find(|probe| probe.stage == *stage && probe.endpoint == *endpoint);
+                if let Some(probe) = probe {
+                    probe.redirects = hops.to_vec();
+                }
+            }
+            ReportEvent::Verdict { verdict } => report.verdict = Some((*verdict).clone()),
+            ReportEvent::PortalFound { url } => report.portal_url = Some(url.to_string()),
+            ReportEvent::PortalOpened { launcher } => {
+                report.opened_with = Some(launcher.to_string())
+            }
+            ReportEvent::AttemptFinished { status, errors } => report.finish(*status, errors),
+            ReportEvent::SessionRemaining { seconds, bytes } => {
+                report.seconds_remaining = *seconds;
+                report.bytes_remaining = *bytes;
+            }
+            ReportEvent::WifiResetStarted { .. } => report.wifi_reset = true,
+            ReportEvent::NetworkNotReady { errors } => {
+                report.finish(ExecutionStatus::NetworkNotReady, errors)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// `3725` → `1h 2m`, `90` → `1m 30s`.
+pub(crate) fn format_seconds(secs: u64) -> String {
+    let (hours, minutes, seconds) = (secs / 3600, secs % 3600 / 60, secs % 60);
+    if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// Binary units with one decimal: `1572864` → `1.5 MB`.
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{Evidence, EvidenceKind, VerdictStrategy};
+
+    /// A stream whose contents stay readable after the reporter took it.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Captured {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn terminal(print_url: bool, verbose: bool) -> (TerminalReporter, Captured, Captured) {
+        let (stdout, stderr) = (Captured::default(), Captured::default());
+        let reporter = TerminalReporter::new(print_url)
+            .verbose(verbose)
+            .output(stdout.clone(), stderr.clone());
+        (reporter, stdout, stderr)
+    }
+
+    fn portal_run(reporter: &TerminalReporter) {
+        let hops = [
+            RedirectHop {
+                url: "http://probe.test/".to_string(),
+                status_code: Some(302),
+            },
+            RedirectHop {
+                url: "https://portal.test/login".to_string(),
+                status_code: None,
+            },
+        ];
+        let verdict = Verdict {
+            strategy: VerdictStrategy::Majority,
+            captive: true,
+            confidence: 100,
+            portal_url: Some("https://portal.test/login".to_string()),
+            evidence: vec![Evidence {
+                kind: EvidenceKind::Redirect,
+                stage: ProbeStage::Standard,
+                endpoint: "Apple".to_string(),
+                url: "http://probe.test/".to_string(),
+                portal_url: Some("https://portal.test/login".to_string()),
+            }],
+        };
+        for event in [
+            ReportEvent::DetectionStarted,
+            ReportEvent::GatewayFound {
+                family: AddressFamily::Ipv6,
+                address: "fe80::1%en0",
+            },
+            ReportEvent::StageStarted {
+                stage: ProbeStage::Standard,
+                endpoints: 1,
+            },
+            ReportEvent::ProbeOutcome {
+                stage: ProbeStage::Standard,
+                family: None,
+                endpoint: "Apple",
+                url: "http://probe.test/",
+                outcome: ProbeOutcome::Portal,
+                status_code: Some(302),
+                elapsed: Duration::from_millis(12),
+                detail: Some("https://portal.test/login"),
+            },
+            ReportEvent::RedirectChain {
+                stage: ProbeStage::Standard,
+                endpoint: "Apple",
+                hops: &hops,
+            },
+            ReportEvent::Verdict { verdict: &verdict },
+            ReportEvent::PortalFound {
+                url: "https://portal.test/login",
+            },
+            ReportEvent::OpeningPortal {
+                url: "https://portal.test/login",
+            },
+            ReportEvent::PortalOpened { launcher: "open" },
+        ] {
+            reporter.report(&event);
+        }
+    }
+
+    #[test]
+    fn print_url_keeps_stdout_to_the_url() {
+        let (reporter, stdout, stderr) = terminal(true, true);
+
+        portal_run(&reporter);
+
+        assert_eq!(stdout.text(), "https://portal.test/login\n");
+        let progress = stderr.text();
+        assert!(progress.contains("Apple redirect detected"));
+        assert!(!progress.contains("Portal URL"));
+    }
+
+    #[test]
+    fn verbose_output_shows_families_hops_and_evidence() {
+        let (reporter, stdout, stderr) = terminal(false, true);
+
+        portal_run(&reporter);
+
+        let output = stdout.text();
+        assert!(output.contains("IPv6 gateway: fe80::1%en0"));
+        assert!(output.contains("302 http://probe.test/"));
+        assert!(output.contains("--- https://portal.test/login"));
+        assert!(output.contains("Verdict (majority): captive, 100% confidence"));
+        assert!(output.contains("redirect from Apple"));
+        assert!(output.contains("Portal URL: https://portal.test/login"));
+        assert!(stderr.text().is_empty());
+
+        let (reporter, stdout, _) = terminal(false, false);
+        portal_run(&reporter);
+        let output = stdout.text();
+        assert!(!output.contains("gateway:"));
+        assert!(!output.contains("http://probe.test/"));
+        assert!(!output.contains("Verdict"));
+    }
+
+    #[test]
+    fn session_limits_are_shown_in_readable_units() {
+        let (reporter, stdout, _) = terminal(false, false);
+
+        reporter.report(&ReportEvent::SessionRemaining {
+            seconds: Some(3720),
+            bytes: Some(1_572_864),
+        });
+        reporter.report(&ReportEvent::SessionRemaining {
+            seconds: Some(61),
+            bytes: Some(512),
+        });
+
+        let output = stdout.text();
+        assert!(output.contains("Session ends in 1h 2m, 1.5 MB of data"));
+        assert!(output.contains("Session ends in 1m 1s, 512 B of data"));
+        assert_eq!(format_seconds(59), "59s");
+    }
+}
@@ -0,0 +1,231 @@
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+
+/// Portal pages are small; anything past this is not read.
+pub const MAX_BODY_BYTES: u64 = 256 * 1024;
+
+/// What a probe got back. The body is only read when asked for, and at most
+/// [`MAX_BODY_BYTES`] of it.
+pub struct ProbeResponse {
+    status: u16,
+    url: String,
+    /// Names in lowercase, in the order they arrived.
+    headers: Vec<(String, String)>,
+    body: Body,
+}
+
+enum Body {
+    Bytes(Vec<u8>),
+    Reader(Box<dyn Read + Send>),
+}
+
+impl ProbeResponse {
+    /// An empty response; the URL is the one that was requested.
+    pub fn new(status: u16, url: impl Into<String>) -> Self {
+        Self {
+            status,
+            url: url.into(),
+            headers: Vec::new(),
+            body: Body::Bytes(Vec::new()),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_ascii_lowercase(), value.into()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = Body::Bytes(body.into());
+        self
+    }
+
+    /// A body read from `reader` on demand.
+    pub fn body_reader(mut self, reader: impl Read + Send + 'static) -> Self {
+        self.body = Body::Reader(Box::new(reader));
+        self
+    }
+
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn is_redirection(&self) -> bool {
+        (300..400).contains(&self.status)
+    }
+
+    /// The first value of the header `name`.
+    pub fn header_value(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Every value of the header `name`, e.g. repeated `Link` headers.
+    pub fn header_values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.headers
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The body as text, cut at [`MAX_BODY_BYTES`]; invalid UTF-8 is replaced.
+    pub fn text(self) -> Result<String, ProbeError> {
+        let mut bytes = Vec::new();
+        match self.body {
+            Body::Bytes(mut body) => {
+                body.truncate(MAX_BODY_BYTES as usize);
+                bytes = body;
+            }
+            Body::Reader(reader) => {
+                reader
+                    .take(MAX_BODY_BYTES)
+                    .read_to_end(&mut bytes)
+                    .map_err(|e| ProbeError::Other(e.to_string()))?;
+            }
+        }
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// A copy for answering the same request again; `None` for a streamed body.
+    pub(crate) fn replay(&self) -> Option<Self> {
+        let Body::Bytes(body) = &self.body else {
+            return None;
+        };
+        Some(Self {
+            status: self.status,
+            url: self.url.clone(),
+            headers: self.headers.clone(),
+            body: Body::Bytes(body.clone()),
+        })
+    }
+
+    /// Rewrites every value of the header `name`.
+    pub(crate) fn map_header(mut self, name: &str, f: impl Fn(&str) -> String) -> Self {
+        for (n, value) in self.headers.iter_mut() {
+            if n.eq_ignore_ascii_case(name) {
+                *value = f(value);
+            }
+        }
+        self
+    }
+
+    pub(crate) fn with_url(mut self, url: &str) -> Self {
+        self.url = url.to_string();
+        self
+    }
+}
+
+impl fmt::Debug for ProbeResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ProbeResponse")
+            .field("status", &self.status)
+            .field("url", &self.url)
+            .field("headers", &self.headers)
+            .finish_non_exhaustive()
+    }
+}
+
+impl From<reqwest::blocking::Response> for ProbeResponse {
+    fn from(response: reqwest::blocking::Response) -> Self {
+        let mut probe = ProbeResponse::new(response.status().as_u16(), response.url().as_str());
+        probe.headers = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((name.as_str().to_string(), value.to_str().ok()?.to_string()))
+            })
+            .collect();
+        probe.body_reader(response)
+    }
+}
+
+/// Why a probe got no response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProbeError {
+    Timeout,
+    Connect,
+    /// Certificate or handshake failure, as when a portal answers for an HTTPS host.
+    Tls(String),
+    Other(String),
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProbeError::Timeout => write!(f, "timed out"),
+            ProbeError::Connect => write!(f, "connect error"),
+            ProbeError::Tls(msg) => write!(f, "TLS error: {}", msg),
+            ProbeError::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl Error for ProbeError {}
+
+impl From<reqwest::Error> for ProbeError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            ProbeError::Timeout
+        } else if let Some(cause) = tls_cause(&err) {
+            ProbeError::Tls(cause)
+        } else if err.is_connect() {
+            ProbeError::Connect
+        } else {
+            ProbeError::Other(err.to_string())
+        }
+    }
+}
+
+/// Certificate and handshake failures; reqwest only exposes them through the source chain.
+fn tls_cause(err: &reqwest::Error) -> Option<String> {
+    let mut source = err.source();
+    while let Some(cause) = source {
+        let text = cause.to_string();
+        let lower = text.to_ascii_lowercase();
+        if ["certificate", "tls", "ssl", "handshake"]
+            .iter()
+            .any(|word| lower.contains(word))
+        {
+            return Some(text);
+        }
+        source = cause.source();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn body_is_read_lazily_and_capped() {
+        let big = vec![b'a'; MAX_BODY_BYTES as usize + 10];
+        let response = ProbeResponse::new(200, "http://probe.test/").body_reader(Cursor::new(big));
+        assert_eq!(response.text().unwrap().len(), MAX_BODY_BYTES as usize);
+
+        let response = ProbeResponse::new(200, "http://probe.test/").body(vec![0xff, b'o', b'k']);
+        assert_eq!(response.text().unwrap(), "\u{fffd}ok");
+    }
+
+    #[test]
+    fn headers_are_case_insensitive_and_repeatable() {
+        let response = ProbeResponse::new(511, "http://probe.test/")
+            .header("Link", "<http://a.test/>")
+            .header("link", "<http://b.test/>");
+        assert_eq!(response.header_value("LINK"), Some("<http://a.test/>"));
+        assert_eq!(response.header_values("link").count(), 2);
+    }
+}
@@ -0,0 +1,481 @@
+//! A local HTTP server that answers like a captive portal, so detection can be
+//! tested end to end with the real [`HttpClient`](crate::HttpClient).
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::{
+    BodyMatch, DetectionConfig, DhcpLeaseSource, EndpointSetting, Options, Settings, WifiBackend,
+    APPLE_SUCCESS_PAGE, MACOS_CONFIG,
+};
+
+/// How the simulated portal treats requests for anything but its login page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimPortal {
+    /// No portal: every check gets the answer it expects.
+    Open,
+    /// `302 Found` to the login page.
+    Redirect,
+    /// A 200 splash page with a meta refresh to the login page.
+    MetaRefresh,
+    /// `511 Network Authentication Required` with a `Link` to the login page.
+    AuthenticationRequired,
+    /// A 200 page that sends the browser on with JavaScript.
+    JsRedirect,
+    /// Accepts connections and never answers.
+    Hang,
+    /// Lets the listed hosts through and redirects the rest to the login page.
+    WalledGarden(Vec<String>),
+}
+
+impl FromStr for SimPortal {
+    type Err = String;
+
+    /// Walled gardens start with no allowed hosts.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open" => Ok(SimPortal::Open),
+            "redirect" => Ok(SimPortal::Redirect),
+            "meta-refresh" => Ok(SimPortal::MetaRefresh),
+            "511" => Ok(SimPortal::AuthenticationRequired),
+            "js-redirect" => Ok(SimPortal::JsRedirect),
+            "hang" => Ok(SimPortal::Hang),
+            "walled-garden" => Ok(SimPortal::WalledGarden(Vec::new())),
+            _ => Err(format!("unknown portal: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for SimPortal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            SimPortal::Open => "open",
+            SimPortal::Redirect => "redirect",
+            SimPortal::MetaRefresh => "meta-refresh",
+            SimPortal::AuthenticationRequired => "511",
+            SimPortal::JsRedirect => "js-redirect",
+            SimPortal::Hang => "hang",
+            SimPortal::WalledGarden(_) => "walled-garden",
+        })
+    }
+}
+
+/// A running simulator; stops when dropped.
+///
+/// The login page is `/login` on whatever host was asked for. Anything else is
+/// answered by the [`SimPortal`], or, when let through, like the real endpoint:
+/// `/hotspot-detect.html` with Apple's success page, `/generate_204` with a 204
+/// and other paths with a router status page.
+pub struct PortalSim {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    accept: Option<JoinHandle<()>>,
+}
+
+impl PortalSim {
+    /// Serves `portal` on a free port of 127.0.0.1.
+    pub fn start(portal: SimPortal) -> io::Result<Self> {
+        Self::bind("127.0.0.1:0", portal)
+    }
+
+    pub fn bind(addr: impl ToSocketAddrs, portal: SimPortal) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let portal = Arc::new(portal);
+
+        let accept = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let Ok(stream) = stream else { continue };
+                    let (portal, stop) = (portal.clone(), stop.clone());
+                    thread::spawn(move || serve(stream, &portal, &stop));
+                }
+            })
+        };
+
+        Ok(Self {
+            addr,
+            stop,
+            accept: Some(accept),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Config overrides that point detection at the simulator: Apple's check on
+    /// `127.0.0.1`, Google's on `localhost`, so a walled garden can let one
+    /// through, and the simulator as the gateway.
+    pub fn settings(&self) -> Settings {
+        let port = self.addr.port();
+        Settings {
+            endpoints: vec![
+                EndpointSetting {
+                    name: "Apple".to_string(),
+                    url: format!("http://127.0.0.1:{port}/hotspot-detect.html"),
+                    expected_status: None,
+                    expected_body: Some(BodyMatch::Exact(APPLE_SUCCESS_PAGE.into())),
+                },
+                EndpointSetting {
+                    name: "Google".to_string(),
+                    url: format!("http://localhost:{port}/generate_204"),
+                    expected_status: Some(204),
+                    expected_body: None,
+                },
+            ],
+            gateway: Some(self.addr.to_string()),
+            ..Settings::default()
+        }
+    }
+
+    /// The built-in preset with [`PortalSim::settings`] applied, without Wi-Fi
+    /// control or DHCP lookups.
+    pub fn config(&self) -> DetectionConfig {
+        let preset = MACOS_CONFIG
+            .to_builder()
+            .clear_endpoints()
+            .wifi_backend(WifiBackend::None)
+            .dhcp_leases(DhcpLeaseSource::None)
+            .build();
+        self.settings().apply(preset, &mut Options::default())
+    }
+}
+
+impl Drop for PortalSim {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Wakes the accept loop so it sees the flag.
+        let _ = TcpStream::connect(self.addr);
+        if let Some(accept) = self.accept.take() {
+            let _ = accept.join();
+        }
+    }
+}
+
+struct Request {
+    path: String,
+    host: String,
+}
+
+fn read_request(stream: &TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let path = line.split_whitespace().nth(1)?.to_string();
+
+    let mut host = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).ok()? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("host") {
+                host = value.trim().to_string();
+            }
+        }
+    }
+    Some(Request { path, host })
+}
+
+fn serve(mut stream: TcpStream, portal: &SimPortal, stop: &AtomicBool) {
+    let Some(request) = read_request(&stream) else {
+        return;
+    };
+    if *portal == SimPortal::Hang {
+        while !stop.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(50));
+        }
+        return;
+    }
+    let _ = stream.write_all(respond(portal, &request).as_bytes());
+}
+
+const LOGIN_PAGE: &str = r#"<html><body><form method="post" action="/login"><input name="code"><button>Connect</button></form></body></html>"#;
+const ROUTER_PAGE: &str = "<html><body>Router status: online</body></html>";
+
+fn respond(portal: &SimPortal, request: &Request) -> String {
+    let login = format!("http://{}/login", request.host);
+    let path = request.path.split('?').next().unwrap_or("/");
+
+    if path == "/login" {
+        return reply(200, &[], LOGIN_PAGE);
+    }
+    match portal {
+        SimPortal::Redirect => reply(302, &[("Location", &login)], ""),
+        SimPortal::MetaRefresh => reply(
+            200,
+            &[],
+            r#"<html><head><meta http-equiv="refresh" content="0; url=/login"></head></html>"#,
+        ),
+        SimPortal::AuthenticationRequired => reply(
+            511,
+            &[("Link", &format!("<{login}>; rel=\"captive-portal\""))],
+            "<html><body>Network Authentication Required</body></html>",
+        ),
+        SimPortal::JsRedirect => reply(
+            200,
+            &[],
+            r#"<html><script>window.location.replace("/login");</script></html>"#,
+        ),
+        SimPortal::WalledGarden(allowed) if !allowed.contains(&host_name(&request.host)) => {
+            reply(302, &[("Location", &login)], "")
+        }
+        SimPortal::Open | SimPortal::WalledGarden(_) | SimPortal::Hang => match path {
+            "/hotspot-detect.html" => reply(200, &[], APPLE_SUCCESS_PAGE),
+            "/generate_204" => reply(204, &[], ""),
+            _ => reply(200, &[], ROUTER_PAGE),
+        },
+    }
+}
+
+/// `host:port` or `[v6]:port` without the port.
+fn host_name(host: &str) -> String {
+    match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or(rest).to_string(),
+        None => host.split(':').next().unwrap_or(host).to_string(),
+    }
+}
+
+fn reply(status: u16, headers: &[(&str, &str)], body: &str) -> String {
+    let reason = match status {
+        200 => "OK",
+        204 => "No Content",
+        302 => "Found",
+        511 => "Network Authentication Required",
+        _ => "",
+    };
+    let mut response = format!("HTTP/1.1 {status} {reason}\r\n");
+    for (name, value) in headers {
+        response.push_str(&format!("{name}: {value}\r\n"));
+    }
+    response.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    ));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::testing::{fake_context, RecordingOpener, ScriptedRunner};
+    use crate::{
+        detect_gateway, detect_portal, detect_standard, AddressFamily, DetectionEndpoint,
+        DetectionResult, EvidenceKind, HttpClient, JsonReporter, NetworkClient, ProbeError,
+        SilentReporter, StubResolver, VerdictStrategy,
+    };
+
+    const TIMEOUT_SECS: u64 = 1;
+
+    fn options(verdict: VerdictStrategy) -> Options {
+        Options {
+            timeout: TIMEOUT_SECS,
+            verdict,
+            ..Options::default()
+        }
+    }
+
+    /// Runs full detection against `portal` and returns what was opened.
+    fn opened(portal: SimPortal, options: &Options) -> Vec<String> {
+        let sim = PortalSim::start(portal).unwrap();
+        let config = sim.config();
+        let runner = ScriptedRunner::new(&[]);
+        // Real DNS could be hijacked by whatever network runs the tests.
+        let net = HttpClient::new(TIMEOUT_SECS)
+            .unwrap()
+            .with_resolver(StubResolver::new());
+        let ctx = fake_context(&config, net, &runner, options, &SilentReporter);
+        let opener = RecordingOpener::default();
+        detect_portal(&ctx, &opener);
+        opener.opened()
+    }
+
+    #[test]
+    fn every_portal_style_leads_to_the_login_page() {
+        let options = options(VerdictStrategy::AnyPortal);
+        for portal in [
+            SimPortal::Redirect,
+            SimPortal::MetaRefresh,
+            SimPortal::AuthenticationRequired,
+            SimPortal::JsRedirect,
+        ] {
+            let opened = opened(portal.clone(), &options);
+            assert_eq!(opened.len(), 1, "{portal}");
+            assert!(opened[0].ends_with("/login"), "{portal}: {}", opened[0]);
+        }
+    }
+
+    #[test]
+    fn open_network_has_no_portal() {
+        let sim = PortalSim::start(SimPortal::Open).unwrap();
+        let config = sim.config();
+        let runner = ScriptedRunner::new(&[]);
+        let options = options(VerdictStrategy::AllMustPass);
+        let net = HttpClient::new(TIMEOUT_SECS).unwrap();
+        let ctx = fake_context(&config, net, &runner, &options, &SilentReporter);
+
+        assert_eq!(detect_standard(&ctx), DetectionResult::NoPortalDetected);
+        assert!(!matches!(
+            detect_gateway(&ctx),
+            DetectionResult::PortalFound(_)
+        ));
+    }
+
+    #[test]
+    fn hanging_portal_times_out() {
+        let sim = PortalSim::start(SimPortal::Hang).unwrap();
+        let config = sim.config();
+        let runner = ScriptedRunner::new(&[]);
+        let options = options(VerdictStrategy::AnyPortal);
+        let net = HttpClient::new(TIMEOUT_SECS).unwrap();
+        let ctx = fake_context(&config, net, &runner, &options, &SilentReporter);
+
+        let started = Instant::now();
+        let DetectionResult::NetworkIssues(errors) = detect_standard(&ctx) else {
+            panic!("a hanging portal is not a result");
+        };
+        assert_eq!(errors, ["Apple: timeout (1s)", "Google: timeout (1s)"]);
+        assert!(started.elapsed() < Duration::from_secs(3));
+    }
+
+    #[test]
+    fn walled_garden_depends_on_the_strategy() {
+        let sim = PortalSim::start(SimPortal::WalledGarden(vec!["localhost".into()])).unwrap();
+        // A second check on the allowed host outweighs Apple's redirect.
+        let router = format!("http://localhost:{}/", sim.addr().port());
+        let config = sim
+            .config()
+            .to_builder()
+            .endpoint(DetectionEndpoint::new("Router", router, Some(200)))
+            .build();
+        let runner = ScriptedRunner::new(&[]);
+        let login = format!("http://{}/login", sim.addr());
+
+        let detect = |verdict| {
+            let options = options(verdict);
+            let reporter = JsonReporter::default();
+            let net = HttpClient::new(TIMEOUT_SECS).unwrap();
+            let ctx = fake_context(&config, net, &runner, &options, &reporter);
+            let result = detect_standard(&ctx);
+            (result, reporter.into_report().verdict.unwrap())
+        };
+
+        let (result, _) = detect(VerdictStrategy::AnyPortal);
+        assert_eq!(result, DetectionResult::PortalFound(login.clone()));
+
+        let (result, verdict) = detect(VerdictStrategy::Majority);
+        assert_eq!(result, DetectionResult::NoPortalDetected);
+        assert!(!verdict.captive);
+        let kinds: Vec<EvidenceKind> = verdict.evidence.iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds.iter().filter(|k| **k == EvidenceKind::Passed).count(),
+            2
+        );
+        assert!(kinds.contains(&EvidenceKind::Redirect));
+
+        let (result, _) = detect(VerdictStrategy::AllMustPass);
+        assert_eq!(result, DetectionResult::PortalFound(login));
+    }
+
+    #[test]
+    fn redirects_are_followed_over_real_http() {
+        let options = Options {
+            follow_redirects: 3,
+            ..options(VerdictStrategy::AnyPortal)
+        };
+        let opened = opened(SimPortal::Redirect, &options);
+        assert_eq!(opened.len(), 1);
+        assert!(opened[0].ends_with("/login"));
+    }
+
+    #[test]
+    fn probes_can_be_tied_to_an_address_family() {
+        let sim = PortalSim::start(SimPortal::Open).unwrap();
+        let net = HttpClient::new(TIMEOUT_SECS).unwrap();
+        let timeout = Duration::from_secs(TIMEOUT_SECS);
+        let port = sim.addr().port();
+
+        let url = format!("http://localhost:{port}/generate_204");
+        let response = net.get_over(&url, AddressFamily::Ipv4, timeout).unwrap();
+        assert_eq!(response.status(), 204);
+
+        let url = format!("http://127.0.0.1:{port}/generate_204");
+        let error = net
+            .get_over(&url, AddressFamily::Ipv6, timeout)
+            .unwrap_err();
+        assert_eq!(error, ProbeError::Connect);
+    }
+
+    #[test]
+    fn failed_lookups_are_not_connect_errors() {
+        let net = HttpClient::new(TIMEOUT_SECS).unwrap();
+        let timeout = Duration::from_secs(TIMEOUT_SECS);
+
+        let error = net
+            .get_over("http://reauthfi.invalid/", AddressFamily::Ipv4, timeout)
+            .unwrap_err();
+        let ProbeError::Other(msg) = error else {
+            panic!("expected a lookup error, got {error:?}");
+        };
+        assert!(
+            msg.starts_with("lookup of reauthfi.invalid failed: "),
+            "{msg}"
+        );
+    }
+
+    #[test]
+    fn zoned_gateways_see_their_own_address() {
+        // Runners without IPv6 or a known loopback interface cannot run this.
+        let Ok(sim) = PortalSim::bind("[::1]:0", SimPortal::Redirect) else {
+            return;
+        };
+        let Some(loopback) = ["lo", "lo0"]
+            .into_iter()
+            .find_map(crate::family::interface_index)
+        else {
+            return;
+        };
+        let net = HttpClient::new(TIMEOUT_SECS).unwrap();
+        let timeout = Duration::from_secs(TIMEOUT_SECS);
+        let gateway = format!("http://[::1%25{}]:{}/", loopback, sim.addr().port());
+
+        let response = net.get(&gateway, timeout).unwrap();
+
+        assert_eq!(response.status(), 302);
+        assert_eq!(response.url(), gateway);
+        assert_eq!(
+            response.header_value("location"),
+            Some(format!("{gateway}login").as_str())
+        );
+    }
+
+    #[test]
+    fn portal_names_round_trip() {
+        for name in [
+            "open",
+            "redirect",
+            "meta-refresh",
+            "511",
+            "js-redirect",
+            "hang",
+            "walled-garden",
+        ] {
+            assert_eq!(name.parse::<SimPortal>().unwrap().to_string(), name);
+        }
+    }
+}
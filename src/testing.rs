@@ -0,0 +1,165 @@
+//! Fakes shared by the unit tests of several modules.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{
+    Clock, CommandRunner, ConfigFile, ConfigLayers, DetectionConfig, DetectionContext,
+    DetectionEndpoint, DhcpLeaseSource, FakeNetworkClient, NetworkClient, Options, PortalOpener,
+    ProbeResponse, ReauthfiError, Reporter, Settings, WifiBackend, MACOS_CONFIG,
+};
+
+/// macOS-style config with a single `Probe` endpoint expecting 204, no gateway paths,
+/// no Wi-Fi control and no Captive Portal API lookup.
+pub(crate) fn probe_config() -> DetectionConfig {
+    MACOS_CONFIG
+        .to_builder()
+        .clear_endpoints()
+        .clear_gateway_endpoints()
+        .endpoint(DetectionEndpoint::new(
+            "Probe",
+            "http://probe.test/generate_204",
+            Some(204),
+        ))
+        .wifi_backend(WifiBackend::None)
+        .dhcp_leases(DhcpLeaseSource::None)
+        .build()
+}
+
+/// `preset` with no config file and no flags.
+pub(crate) fn layers(preset: DetectionConfig) -> ConfigLayers {
+    ConfigLayers {
+        preset,
+        file: ConfigFile::default(),
+        cli: Settings::default(),
+        options: Options::default(),
+        source: None,
+    }
+}
+
+pub(crate) type Script = &'static [(&'static str, Result<&'static str, &'static str>)];
+
+/// Answers each command with the first scripted entry whose key prefixes it,
+/// treating unscripted programs as missing, and records every attempt.
+pub(crate) struct ScriptedRunner {
+    script: Vec<(&'static str, Result<&'static str, &'static str>)>,
+    calls: Mutex<Vec<String>>,
+    detached: Mutex<Vec<String>>,
+}
+
+impl ScriptedRunner {
+    pub(crate) fn new(script: &[(&'static str, Result<&'static str, &'static str>)]) -> Self {
+        Self {
+            script: script.to_vec(),
+            calls: Mutex::new(Vec::new()),
+            detached: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// The attempts that went through [`CommandRunner::run_detached`].
+    pub(crate) fn detached(&self) -> Vec<String> {
+        self.detached.lock().unwrap().clone()
+    }
+}
+
+impl CommandRunner for ScriptedRunner {
+    fn run(&self, cmd: &[&str]) -> Result<String, std::io::Error> {
+        let joined = cmd.join(" ");
+        self.calls.lock().unwrap().push(joined.clone());
+        match self.script.iter().find(|(key, _)| joined.starts_with(key)) {
+            Some((_, Ok(output))) => Ok(output.to_string()),
+            Some((_, Err(msg))) => Err(std::io::Error::other(msg.to_string())),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "not installed",
+            )),
+        }
+    }
+
+    fn run_detached(&self, cmd: &[&str]) -> Result<(), std::io::Error> {
+        self.detached.lock().unwrap().push(cmd.join(" "));
+        self.run(cmd).map(|_| ())
+    }
+}
+
+/// Answers the probe of [`probe_config`] in turn: `Some(url)` redirects there,
+/// `None` is a 204. Once the script runs out every check passes.
+pub(crate) fn portal_script(answers: &[Option<&str>]) -> FakeNetworkClient {
+    let url = "http://probe.test/generate_204";
+    answers
+        .iter()
+        .fold(FakeNetworkClient::new(), |net, answer| match answer {
+            Some(location) => net.redirect(url, 302, location),
+            None => net.respond(url, ProbeResponse::new(204, url)),
+        })
+        .respond(url, ProbeResponse::new(204, url))
+}
+
+#[derive(Default)]
+pub(crate) struct RecordingOpener {
+    pub(crate) opened: Mutex<Vec<String>>,
+}
+
+impl RecordingOpener {
+    pub(crate) fn opened(&self) -> Vec<String> {
+        self.opened.lock().unwrap().clone()
+    }
+}
+
+impl PortalOpener for RecordingOpener {
+    fn open(&self, url: &str) -> Result<String, ReauthfiError> {
+        self.opened.lock().unwrap().push(url.to_string());
+        Ok("test".to_string())
+    }
+}
+
+/// A detection context over a fake network; pass an `Arc` to keep a handle on it.
+pub(crate) fn fake_context<'a, N: NetworkClient + 'static>(
+    config: &'a DetectionConfig,
+    net: impl Into<Arc<N>>,
+    runner: &'a dyn CommandRunner,
+    options: &'a Options,
+    reporter: &'a dyn Reporter,
+) -> DetectionContext<'a> {
+    DetectionContext {
+        config,
+        net: net.into(),
+        commands: runner,
+        options,
+        reporter,
+    }
+}
+
+/// Starts at a real instant and only moves when slept on.
+pub(crate) struct FakeClock {
+    now: Mutex<Instant>,
+    slept: Mutex<Vec<Duration>>,
+}
+
+impl FakeClock {
+    pub(crate) fn new() -> Self {
+        Self {
+            now: Mutex::new(Instant::now()),
+            slept: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn slept(&self) -> Vec<Duration> {
+        self.slept.lock().unwrap().clone()
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+        self.slept.lock().unwrap().push(duration);
+    }
+}
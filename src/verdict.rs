@@ -0,0 +1,303 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ProbeStage;
+
+/// How probe evidence is turned into a verdict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VerdictStrategy {
+    /// Any probe that leads to a portal wins, as soon as it answers; a DNS
+    /// answer only while no standard probe got through.
+    #[default]
+    AnyPortal,
+    /// Captive when the evidence for a portal outweighs the probes that passed.
+    Majority,
+    /// Open only when every probe that answered passed; any anomaly means captive.
+    AllMustPass,
+}
+
+impl FromStr for VerdictStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "any-portal" => Ok(VerdictStrategy::AnyPortal),
+            "majority" => Ok(VerdictStrategy::Majority),
+            "all-must-pass" => Ok(VerdictStrategy::AllMustPass),
+            _ => Err(format!("unknown verdict strategy: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for VerdictStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            VerdictStrategy::AnyPortal => "any-portal",
+            VerdictStrategy::Majority => "majority",
+            VerdictStrategy::AllMustPass => "all-must-pass",
+        })
+    }
+}
+
+/// What a single check observed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EvidenceKind {
+    /// A redirect where the endpoint answers directly.
+    Redirect,
+    /// HTTP 511.
+    AuthenticationRequired,
+    /// A page in place of the endpoint's answer, leading to a login page.
+    InjectedPage,
+    /// The expected status with a different page and no link to follow.
+    BodyMismatch,
+    /// An unexpected status and nothing to follow.
+    StatusMismatch,
+    /// TLS failed on an HTTPS endpoint, as when a portal answers for the host.
+    TlsAnomaly,
+    /// A name resolved to where it should not.
+    DnsAnomaly,
+    /// The endpoint answered exactly as expected.
+    Passed,
+}
+
+impl fmt::Display for EvidenceKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            EvidenceKind::Redirect => "redirect",
+            EvidenceKind::AuthenticationRequired => "authentication_required",
+            EvidenceKind::InjectedPage => "injected_page",
+            EvidenceKind::BodyMismatch => "body_mismatch",
+            EvidenceKind::StatusMismatch => "status_mismatch",
+            EvidenceKind::TlsAnomaly => "tls_anomaly",
+            EvidenceKind::DnsAnomaly => "dns_anomaly",
+            EvidenceKind::Passed => "passed",
+        })
+    }
+}
+
+impl EvidenceKind {
+    /// How strongly this points at a portal; `Passed` is weighed against them.
+    fn weight(self) -> u32 {
+        match self {
+            EvidenceKind::Redirect | EvidenceKind::AuthenticationRequired => 3,
+            EvidenceKind::InjectedPage | EvidenceKind::BodyMismatch => 2,
+            EvidenceKind::DnsAnomaly | EvidenceKind::Passed => 2,
+            EvidenceKind::StatusMismatch | EvidenceKind::TlsAnomaly => 1,
+        }
+    }
+
+    pub fn is_anomaly(self) -> bool {
+        self != EvidenceKind::Passed
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Evidence {
+    pub kind: EvidenceKind,
+    pub stage: ProbeStage,
+    pub endpoint: String,
+    /// What was checked: the probe URL, or the name that was resolved.
+    pub url: String,
+    /// Where the check leads, when it found a login page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub portal_url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Verdict {
+    pub strategy: VerdictStrategy,
+    pub captive: bool,
+    /// Share of the weighed evidence that agrees with `captive`, in percent.
+    pub confidence: u8,
+    /// The best login page found; the URL of the strongest anomaly when none was.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub portal_url: Option<String>,
+    pub evidence: Vec<Evidence>,
+}
+
+/// Weighs `evidence` with `strategy`. `None` when there is nothing to weigh,
+/// e.g. when every probe failed.
+pub fn decide_verdict(strategy: VerdictStrategy, evidence: &[Evidence]) -> Option<Verdict> {
+    if evidence.is_empty() {
+        return None;
+    }
+
+    let (anomalies, passed): (Vec<&Evidence>, Vec<&Evidence>) =
+        evidence.iter().partition(|e| e.kind.is_anomaly());
+    let against: u32 = anomalies.iter().map(|e| e.kind.weight()).sum();
+    let clean: u32 = passed.iter().map(|e| e.kind.weight()).sum();
+
+    // A name resolving to a portal says nothing once HTTP got through.
+    let overruled = |e: &&Evidence| {
+        strategy == VerdictStrategy::AnyPortal
+            && e.kind == EvidenceKind::DnsAnomaly
+            && !passed.is_empty()
+    };
+
+    let captive = match strategy {
+        VerdictStrategy::AnyPortal => anomalies
+            .iter()
+            .any(|e| e.portal_url.is_some() && !overruled(e)),
+        VerdictStrategy::Majority => against > clean,
+        VerdictStrategy::AllMustPass => !anomalies.is_empty(),
+    };
+    let agreeing = if captive { against } else { clean };
+    let confidence = (agreeing * 100 / (against + clean)) as u8;
+
+    // Strongest first; among equals, the first to arrive.
+    let mut ranked = anomalies;
+    ranked.sort_by_key(|e| std::cmp::Reverse(e.kind.weight()));
+    let portal_url = captive
+        .then(|| {
+            ranked
+                .iter()
+                .filter(|e| !overruled(e))
+                .find_map(|e| e.portal_url.clone())
+                // A resolved name is no page to open; only a probe URL is.
+                .or_else(|| {
+                    ranked
+                        .iter()
+                        .find(|e| e.stage != ProbeStage::Dns)
+                        .map(|e| e.url.clone())
+                })
+        })
+        .flatten();
+
+    Some(Verdict {
+        strategy,
+        captive,
+        confidence,
+        portal_url,
+        evidence: evidence.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evidence(kind: EvidenceKind, endpoint: &str, portal_url: Option<&str>) -> Evidence {
+        Evidence {
+            kind,
+            stage: ProbeStage::Standard,
+            endpoint: endpoint.to_string(),
+            url: format!("http://{}.test/", endpoint.to_ascii_lowercase()),
+            portal_url: portal_url.map(str::to_string),
+        }
+    }
+
+    /// Apple's host is let through while Google's answer is replaced by a page.
+    fn walled_garden() -> Vec<Evidence> {
+        vec![
+            evidence(EvidenceKind::Passed, "Apple", None),
+            evidence(EvidenceKind::BodyMismatch, "Google", None),
+        ]
+    }
+
+    fn evidence_redirect() -> Evidence {
+        evidence(
+            EvidenceKind::Redirect,
+            "Gateway",
+            Some("http://portal.test/login"),
+        )
+    }
+
+    #[test]
+    fn strategies_weigh_the_same_evidence_differently() {
+        let evidence = walled_garden();
+
+        let any = decide_verdict(VerdictStrategy::AnyPortal, &evidence).unwrap();
+        assert!(!any.captive);
+        assert_eq!(any.confidence, 50);
+
+        let majority = decide_verdict(VerdictStrategy::Majority, &evidence).unwrap();
+        assert!(!majority.captive);
+
+        let strict = decide_verdict(VerdictStrategy::AllMustPass, &evidence).unwrap();
+        assert!(strict.captive);
+        assert_eq!(strict.confidence, 50);
+        assert_eq!(strict.portal_url.as_deref(), Some("http://google.test/"));
+    }
+
+    #[test]
+    fn majority_follows_the_heavier_side() {
+        let mut evidence = walled_garden();
+        evidence.push(evidence_redirect());
+
+        let verdict = decide_verdict(VerdictStrategy::Majority, &evidence).unwrap();
+        assert!(verdict.captive);
+        assert_eq!(verdict.confidence, 71);
+        assert_eq!(
+            verdict.portal_url.as_deref(),
+            Some("http://portal.test/login")
+        );
+    }
+
+    #[test]
+    fn any_portal_needs_a_login_page() {
+        let evidence = vec![
+            evidence(EvidenceKind::StatusMismatch, "Apple", None),
+            evidence_redirect(),
+        ];
+        let verdict = decide_verdict(VerdictStrategy::AnyPortal, &evidence).unwrap();
+        assert!(verdict.captive);
+        assert_eq!(verdict.confidence, 100);
+    }
+
+    #[test]
+    fn any_portal_trusts_http_over_dns() {
+        let hijack = Evidence {
+            stage: ProbeStage::Dns,
+            ..evidence(
+                EvidenceKind::DnsAnomaly,
+                "probe.test",
+                Some("http://10.0.0.1/login"),
+            )
+        };
+        let verdict =
+            decide_verdict(VerdictStrategy::AnyPortal, std::slice::from_ref(&hijack)).unwrap();
+        assert_eq!(verdict.portal_url.as_deref(), Some("http://10.0.0.1/login"));
+
+        let evidence = [hijack, evidence(EvidenceKind::Passed, "Apple", None)];
+        let verdict = decide_verdict(VerdictStrategy::AnyPortal, &evidence).unwrap();
+        assert!(!verdict.captive);
+        assert_eq!(verdict.portal_url, None);
+    }
+
+    #[test]
+    fn nothing_to_weigh_gives_no_verdict() {
+        assert_eq!(decide_verdict(VerdictStrategy::Majority, &[]), None);
+    }
+
+    #[test]
+    fn strategy_names_round_trip() {
+        for strategy in [
+            VerdictStrategy::AnyPortal,
+            VerdictStrategy::Majority,
+            VerdictStrategy::AllMustPass,
+        ] {
+            assert_eq!(strategy.to_string().parse(), Ok(strategy));
+        }
+        assert!("most".parse::<VerdictStrategy>().is_err());
+    }
+
+    #[test]
+    fn evidence_kinds_display_as_in_json() {
+        for kind in [
+            EvidenceKind::Redirect,
+            EvidenceKind::AuthenticationRequired,
+            EvidenceKind::InjectedPage,
+            EvidenceKind::BodyMismatch,
+            EvidenceKind::StatusMismatch,
+            EvidenceKind::TlsAnomaly,
+            EvidenceKind::DnsAnomaly,
+            EvidenceKind::Passed,
+        ] {
+            assert_eq!(serde_json::to_value(kind).unwrap(), kind.to_string());
+        }
+    }
+}
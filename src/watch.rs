@@ -0,0 +1,454 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::{
+    detect_captive_api, detect_standard, portal_opener, CaptivePortalStatus, CommandRunner,
+    ConfigLayers, DetectionContext, DetectionResult, EffectiveConfig, HttpClient, NetworkClient,
+    Options, PortalOpener, ReauthfiError, ReportEvent, Reporter, SilentReporter,
+    SystemCommandRunner,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchState {
+    Online,
+    Captive,
+    Unreachable,
+}
+
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    /// Delay between checks while the state keeps changing or the network is online.
+    pub interval: Duration,
+    /// Upper bound for the delay after repeated captive or unreachable results.
+    pub max_interval: Duration,
+    /// Minimum time before the same portal is opened again.
+    pub cooldown: Duration,
+    /// How long before a session reported by the Captive Portal API ends to open its
+    /// portal, so it can be renewed before the connection drops.
+    pub expiry_margin: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+            max_interval: Duration::from_secs(300),
+            cooldown: Duration::from_secs(600),
+            expiry_margin: Duration::from_secs(60),
+        }
+    }
+}
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
+}
+
+/// Re-runs standard detection and opens the portal when the network turns captive.
+pub struct Watcher<'a> {
+    layers: &'a ConfigLayers,
+    net: Arc<dyn NetworkClient>,
+    commands: &'a dyn CommandRunner,
+    /// Used instead of the launcher the resolved options name.
+    opener: Option<&'a dyn PortalOpener>,
+    reporter: &'a dyn Reporter,
+    clock: &'a dyn Clock,
+    watch: WatchOptions,
+    state: Option<WatchState>,
+    delay: Duration,
+    last_opened: HashMap<String, Instant>,
+    /// Whether the end of the current session was already acted on.
+    expiry_handled: bool,
+}
+
+impl<'a> Watcher<'a> {
+    pub fn new(
+        layers: &'a ConfigLayers,
+        net: Arc<dyn NetworkClient>,
+        commands: &'a dyn CommandRunner,
+        reporter: &'a dyn Reporter,
+        clock: &'a dyn Clock,
+        watch: WatchOptions,
+    ) -> Self {
+        let delay = watch.interval;
+        Self {
+            layers,
+            net,
+            commands,
+            opener: None,
+            reporter,
+            clock,
+            watch,
+            state: None,
+            delay,
+            last_opened: HashMap::new(),
+            expiry_handled: false,
+        }
+    }
+
+    /// Opens portals with `opener` whatever the network's profile says.
+    pub fn opener(mut self, opener: &'a dyn PortalOpener) -> Self {
+        self.opener = Some(opener);
+        self
+    }
+
+    pub fn state(&self) -> Option<WatchState> {
+        self.state
+    }
+
+    /// Runs one detection, acts on state changes and returns the delay before the next check.
+    pub fn check(&mut self) -> Duration {
+        // The machine may have moved to another network since the last check.
+        let effective = self.layers.resolve_current(self.commands);
+        // Per-probe progress would repeat every interval; only state changes are reported.
+        let ctx = DetectionContext {
+            config: &effective.config,
+            net: self.net.clone(),
+            commands: self.commands,
+            options: &effective.options,
+            reporter: &SilentReporter,
+        };
+
+        let result = detect_captive_api(&ctx).unwrap_or_else(|| detect_standard(&ctx));
+        let (state, portal_url, session) = match result {
+            DetectionResult::PortalFound(url) => (WatchState::Captive, Some(url), None),
+            DetectionResult::SessionActive(status) => (WatchState::Online, None, Some(status)),
+            DetectionResult::NoPortalDetected => (WatchState::Online, None, None),
+            DetectionResult::NetworkIssues(_) => (WatchState::Unreachable, None, None),
+        };
+
+        let changed = self.state != Some(state);
+        if changed {
+            self.reporter.report(&ReportEvent::WatchStateChanged {
+                state,
+                portal_url: portal_url.as_deref(),
+            });
+            // Only an online network turning captive is a session that ended; a portal
+            // seen at startup or after an outage is left to `reauthfi` itself.
+            if let (Some(url), Some(WatchState::Online)) = (&portal_url, self.state) {
+                self.on_captive(url, &effective.options);
+            }
+        }
+        self.state = Some(state);
+
+        self.delay = if changed || state == WatchState::Online {
+            self.watch.interval
+        } else {
+            (self.delay * 2).min(self.watch.max_interval)
+        };
+        if let Some(status) = session {
+            self.before_expiry(&status, &effective);
+        }
+        self.delay
+    }
+
+    /// Checks again just before the session ends, and once it is about to, opens the
+    /// portal early so it can be renewed.
+    fn before_expiry(&mut self, status: &CaptivePortalStatus, effective: &EffectiveConfig) {
+        let Some(secs) = status.seconds_remaining else {
+            return;
+        };
+        let remaining = Duration::from_secs(secs);
+        let margin = effective.expiry_margin.unwrap_or(self.watch.expiry_margin);
+        if remaining > margin {
+            self.expiry_handled = false;
+            self.delay = self.delay.min(remaining - margin);
+            return;
+        }
+
+        if !self.expiry_handled {
+            self.expiry_handled = true;
+            if let Some(url) = &status.user_portal_url {
+                if status.can_extend_session != Some(false) {
+                    self.reporter.report(&ReportEvent::SessionRemaining {
+                        seconds: status.seconds_remaining,
+                        bytes: status.bytes_remaining,
+                    });
+                    self.open_portal(url, &effective.options);
+                }
+            }
+        }
+        // Right after expiry the API reports the portal and the usual transition applies.
+        // An API that keeps reporting an ended session is checked at the usual pace.
+        if !remaining.is_zero() {
+            self.delay = self.delay.min(remaining + Duration::from_secs(1));
+        }
+    }
+
+    pub fn run(&mut self) -> ! {
+        loop {
+            let delay = self.check();
+            self.clock.sleep(delay);
+        }
+    }
+
+    fn on_captive(&mut self, url: &str, options: &Options) {
+        self.reporter.report(&ReportEvent::PortalFound { url });
+        self.open_portal(url, options);
+    }
+
+    /// Opens `url` unless opening is disabled or its portal is still cooling down.
+    fn open_portal(&mut self, url: &str, options: &Options) {
+        if !options.open {
+            return;
+        }
+
+        let key = portal_key(url);
+        let now = self.clock.now();
+        if let Some(opened_at) = self.last_opened.get(&key) {
+            if now.duration_since(*opened_at) < self.watch.cooldown {
+                self.reporter
+                    .report(&ReportEvent::PortalOpenSkipped { url });
+                return;
+            }
+        }
+
+        self.reporter.report(&ReportEvent::OpeningPortal { url });
+        let configured;
+        let opener = match self.opener {
+            Some(opener) => opener,
+            None => {
+                configured = portal_opener(options, self.commands);
+                configured.as_ref()
+            }
+        };
+        match opener.open(url) {
+            Ok(launcher) => {
+                self.last_opened.insert(key, now);
+                self.reporter.report(&ReportEvent::PortalOpened {
+                    launcher: &launcher,
+                });
+            }
+            Err(e) => self.reporter.report(&ReportEvent::PortalOpenFailed {
+                error: &e.to_string(),
+            }),
+        }
+    }
+}
+
+/// Portals put session tokens in the query string, so "the same portal" means the same origin.
+fn portal_key(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) => parsed.origin().ascii_serialization(),
+        Err(_) => url.to_string(),
+    }
+}
+
+/// Watches the network until the process is stopped, applying the profiles of
+/// whichever network each check finds.
+pub fn watch(
+    layers: &ConfigLayers,
+    watch: WatchOptions,
+    reporter: &dyn Reporter,
+) -> Result<(), ReauthfiError> {
+    let net = Arc::new(HttpClient::new(layers.options.timeout)?);
+    let commands = SystemCommandRunner;
+
+    reporter.report(&ReportEvent::WatchStarted {
+        interval: watch.interval,
+    });
+    Watcher::new(layers, net, &commands, reporter, &SystemClock, watch).run()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        layers, portal_script, probe_config, FakeClock, RecordingOpener, ScriptedRunner,
+    };
+    use crate::{DhcpLeaseSource, FakeNetworkClient};
+
+    fn watch_options() -> WatchOptions {
+        WatchOptions {
+            interval: Duration::from_secs(10),
+            max_interval: Duration::from_secs(40),
+            cooldown: Duration::from_secs(100),
+            expiry_margin: Duration::from_secs(30),
+        }
+    }
+
+    /// Runs one check per scripted answer, sleeping the returned delay in between.
+    fn watch_script(answers: &[Option<&'static str>]) -> (Vec<String>, Vec<u64>) {
+        let layers = layers(probe_config());
+        let opener = RecordingOpener::default();
+        let clock = FakeClock::new();
+        let commands = ScriptedRunner::new(&[]);
+        let mut watcher = Watcher::new(
+            &layers,
+            Arc::new(portal_script(answers)),
+            &commands,
+            &SilentReporter,
+            &clock,
+            watch_options(),
+        )
+        .opener(&opener);
+
+        let mut delays = Vec::new();
+        for _ in answers {
+            let delay = watcher.check();
+            delays.push(delay.as_secs());
+            clock.sleep(delay);
+        }
+        (opener.opened(), delays)
+    }
+
+    #[test]
+    fn opens_only_when_turning_captive() {
+        let (opened, delays) = watch_script(&[
+            None,
+            Some("http://portal.test/login?s=1"),
+            Some("http://portal.test/login?s=1"),
+            Some("http://portal.test/login?s=1"),
+            None,
+        ]);
+
+        assert_eq!(opened, vec!["http://portal.test/login?s=1"]);
+        assert_eq!(delays, vec![10, 10, 20, 40, 10]);
+    }
+
+    #[test]
+    fn captive_at_startup_is_not_opened() {
+        let (opened, _) = watch_script(&[
+            Some("http://portal.test/login?s=1"),
+            Some("http://portal.test/login?s=1"),
+        ]);
+        assert!(opened.is_empty());
+    }
+
+    #[test]
+    fn same_portal_is_not_reopened_within_cooldown() {
+        let (opened, _) = watch_script(&[
+            None,
+            Some("http://portal.test/login?s=1"),
+            None,
+            Some("http://portal.test/login?s=2"),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some("http://portal.test/login?s=3"),
+        ]);
+
+        assert_eq!(
+            opened,
+            vec![
+                "http://portal.test/login?s=1",
+                "http://portal.test/login?s=3",
+            ]
+        );
+    }
+
+    #[test]
+    fn portal_key_ignores_path_and_query() {
+        assert_eq!(
+            portal_key("http://10.0.0.1:8080/login?token=abc"),
+            "http://10.0.0.1:8080"
+        );
+        assert_eq!(portal_key("not a url"), "not a url");
+    }
+
+    /// One check against a Captive Portal API answering `api_json`.
+    fn check_session(api_json: &'static str) -> (Vec<String>, Duration) {
+        let (opened, delays) = check_session_times(api_json, 1);
+        (opened, delays[0])
+    }
+
+    /// `checks` checks against a Captive Portal API that keeps answering `api_json`.
+    fn check_session_times(api_json: &'static str, checks: usize) -> (Vec<String>, Vec<Duration>) {
+        let config = probe_config()
+            .to_builder()
+            .dhcp_leases(DhcpLeaseSource::Ipconfig)
+            .build();
+        let commands = ScriptedRunner::new(&[
+            ("route -n get default", Ok("  interface: en0\n")),
+            (
+                "ipconfig getpacket en0",
+                Ok("captive_portal_URL (string): https://capport.test/api\n"),
+            ),
+        ]);
+        let layers = layers(config);
+        let opener = RecordingOpener::default();
+        let clock = FakeClock::new();
+        let mut watcher = Watcher::new(
+            &layers,
+            Arc::new(
+                FakeNetworkClient::new()
+                    .page("https://capport.test/api", 200, api_json)
+                    .otherwise(204),
+            ),
+            &commands,
+            &SilentReporter,
+            &clock,
+            watch_options(),
+        )
+        .opener(&opener);
+
+        let delays = (0..checks)
+            .map(|_| {
+                let delay = watcher.check();
+                clock.sleep(delay);
+                delay
+            })
+            .collect();
+        (opener.opened(), delays)
+    }
+
+    #[test]
+    fn session_expiry_shortens_the_delay() {
+        let (opened, delay) = check_session(r#"{"captive": false, "seconds-remaining": 35}"#);
+        assert!(opened.is_empty());
+        assert_eq!(delay, Duration::from_secs(5));
+
+        let (opened, delay) = check_session(r#"{"captive": false, "seconds-remaining": 3600}"#);
+        assert!(opened.is_empty());
+        assert_eq!(delay, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn expiring_session_opens_portal_early() {
+        let (opened, delay) = check_session(
+            r#"{"captive": false, "seconds-remaining": 4,
+                "user-portal-url": "https://login.test/renew"}"#,
+        );
+        assert_eq!(opened, vec!["https://login.test/renew"]);
+        assert_eq!(delay, Duration::from_secs(5));
+
+        let (opened, _) = check_session(
+            r#"{"captive": false, "seconds-remaining": 4, "can-extend-session": false,
+                "user-portal-url": "https://login.test/renew"}"#,
+        );
+        assert!(opened.is_empty());
+    }
+
+    #[test]
+    fn ended_session_is_not_polled_every_second() {
+        let (opened, delays) = check_session_times(
+            r#"{"captive": false, "seconds-remaining": 0,
+                "user-portal-url": "https://login.test/renew"}"#,
+            3,
+        );
+        assert_eq!(opened, vec!["https://login.test/renew"]);
+        assert_eq!(delays, vec![Duration::from_secs(10); 3]);
+    }
+}